@@ -0,0 +1,14 @@
+CREATE TABLE api_tokens (
+    token_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- Tokens are high-entropy random strings: a plain SHA-256 digest is enough
+    -- to make a leaked table useless, without paying argon2 on every request.
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL,
+    PRIMARY KEY (token_id)
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
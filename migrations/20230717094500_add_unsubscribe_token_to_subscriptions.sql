@@ -0,0 +1,11 @@
+-- Every subscriber gets a long-lived token, embedded in the footer of the
+-- emails they receive, to unsubscribe without logging in.
+BEGIN;
+ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+-- Backfill historical entries
+UPDATE subscriptions
+SET unsubscribe_token = substr(md5(random()::text || id::text), 1, 25)
+WHERE unsubscribe_token IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+COMMIT;
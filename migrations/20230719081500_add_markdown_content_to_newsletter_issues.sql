@@ -0,0 +1,4 @@
+-- Issues written in the admin UI are authored in Markdown:
+-- `html_content` and `text_content` are rendered from it.
+-- Issues published through the API only have HTML and text contents.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,7 @@
+-- Issues are only listed in the public archive if the editor opted in.
+ALTER TABLE newsletter_issues ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE;
+-- Existing issues are private: their id is a good enough slug.
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+UPDATE newsletter_issues SET slug = newsletter_issue_id::text;
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
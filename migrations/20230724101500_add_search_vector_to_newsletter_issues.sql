@@ -0,0 +1,9 @@
+-- Titles weigh more than the body when ranking search results.
+-- A generated column keeps the vector in sync with the issue contents.
+ALTER TABLE newsletter_issues ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A') ||
+        setweight(to_tsvector('english', text_content), 'B')
+    ) STORED;
+CREATE INDEX newsletter_issues_search_vector_idx
+    ON newsletter_issues USING GIN (search_vector);
@@ -0,0 +1,27 @@
+-- `published_at` was filled with `now()` but stored as text.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+CREATE INDEX newsletter_issues_published_at_idx ON newsletter_issues (published_at DESC);
+
+-- Issues published before this migration have no known author.
+ALTER TABLE newsletter_issues
+    ADD COLUMN author_user_id uuid NULL REFERENCES users (user_id),
+    ADD COLUMN created_at timestamptz NULL,
+    ADD COLUMN updated_at timestamptz NULL,
+    ADD COLUMN status TEXT NULL;
+UPDATE newsletter_issues
+SET
+    created_at = published_at,
+    updated_at = published_at,
+    status = CASE
+        WHEN EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+        ) THEN 'delivering'
+        ELSE 'delivered'
+    END;
+ALTER TABLE newsletter_issues
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at SET NOT NULL,
+    ALTER COLUMN status SET NOT NULL,
+    ADD CONSTRAINT newsletter_issues_status_check CHECK (status IN ('delivering', 'delivered'));
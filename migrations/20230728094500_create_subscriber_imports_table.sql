@@ -0,0 +1,10 @@
+-- The outcome of a CSV import, kept for the admin to download the rows
+-- that could not be imported.
+CREATE TABLE subscriber_imports(
+    import_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    n_imported INT NOT NULL,
+    n_errors INT NOT NULL,
+    error_report TEXT NOT NULL
+);
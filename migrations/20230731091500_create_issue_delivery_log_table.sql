@@ -0,0 +1,10 @@
+-- Tasks are deleted from `issue_delivery_queue` once executed:
+-- the outcome of every delivery attempt is kept here.
+CREATE TABLE issue_delivery_log(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('sent', 'failed', 'skipped')),
+    attempted_at timestamptz NOT NULL
+);
+CREATE INDEX issue_delivery_log_attempted_at_idx ON issue_delivery_log (attempted_at);
@@ -0,0 +1,12 @@
+-- Links emailed to people asking for their data: only a digest of the token is stored.
+CREATE TABLE data_request_tokens(
+    token_hash TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+-- Addresses erased on request, as a keyed digest: it can be matched against
+-- an address we are given, but the address cannot be recovered from it.
+CREATE TABLE erased_subscribers(
+    email_digest TEXT PRIMARY KEY,
+    erased_at timestamptz NOT NULL
+);
@@ -0,0 +1,30 @@
+-- Proof of consent: one row per opt-in or opt-out.
+-- There is no foreign key on `subscriber_id`: the history outlives a deleted subscription.
+CREATE TABLE consent_events(
+    consent_event_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL,
+    email TEXT NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('subscribed', 'confirmed', 'unsubscribed')),
+    -- Where the event comes from, e.g. the form a subscription was made with.
+    source TEXT NOT NULL,
+    privacy_policy_version TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id, occurred_at);
+
+-- The table is append-only. Rows can only be deleted to erase the data of a
+-- subscriber on request, by setting `zero2prod.erasing` for the transaction.
+CREATE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND current_setting('zero2prod.erasing', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+    BEFORE UPDATE OR DELETE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_changes();
@@ -0,0 +1,16 @@
+-- Tokens are high-entropy random strings: like API tokens, only their
+-- SHA-256 digest is stored. They are deleted once used.
+ALTER TABLE subscription_tokens ADD COLUMN token_hash TEXT NULL;
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NULL;
+ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NULL;
+UPDATE subscription_tokens
+SET token_hash = encode(sha256(convert_to(subscription_token, 'UTF8')), 'hex'),
+    created_at = now(),
+    expires_at = now() + interval '72 hours';
+ALTER TABLE subscription_tokens ALTER COLUMN token_hash SET NOT NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at SET NOT NULL;
+-- Drops the primary key along with it.
+ALTER TABLE subscription_tokens DROP COLUMN subscription_token;
+ALTER TABLE subscription_tokens ADD PRIMARY KEY (token_hash);
+CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id, created_at);
@@ -0,0 +1,4 @@
+-- Mirrors `SubscriptionStatus`: the transitions between them are enforced by the application.
+ALTER TABLE subscriptions
+    ADD CONSTRAINT subscriptions_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced'));
@@ -0,0 +1,13 @@
+-- When the subscription last became pending, to remind and eventually forget
+-- people who never confirm it. `subscribed_at` is kept when subscribing again.
+ALTER TABLE subscriptions ADD COLUMN pending_since TIMESTAMPTZ;
+UPDATE subscriptions s
+SET pending_since = COALESCE(
+    (SELECT max(t.created_at) FROM subscription_tokens t WHERE t.subscriber_id = s.id),
+    s.subscribed_at
+);
+ALTER TABLE subscriptions ALTER COLUMN pending_since SET NOT NULL;
+ALTER TABLE subscriptions ALTER COLUMN pending_since SET DEFAULT now();
+ALTER TABLE subscriptions ADD COLUMN reminder_sent_at TIMESTAMPTZ NULL;
+CREATE INDEX subscriptions_pending_since_idx ON subscriptions (pending_since)
+    WHERE status = 'pending_confirmation';
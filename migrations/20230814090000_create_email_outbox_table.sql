@@ -0,0 +1,11 @@
+-- Emails to subscribers, queued in the transaction that calls for them and
+-- sent by the background worker. Rows are deleted once sent.
+CREATE TABLE email_outbox (
+    email_outbox_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('confirmation', 'already_subscribed')),
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX email_outbox_execute_after_idx ON email_outbox (execute_after);
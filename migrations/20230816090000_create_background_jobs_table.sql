@@ -0,0 +1,55 @@
+-- Asynchronous work of every kind, executed by the background worker.
+-- Jobs are deleted once they have run.
+CREATE TABLE background_jobs (
+    job_id uuid PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- Higher first.
+    priority SMALLINT NOT NULL DEFAULT 0,
+    run_at TIMESTAMPTZ NOT NULL,
+    n_attempts SMALLINT NOT NULL DEFAULT 0,
+    max_attempts SMALLINT NOT NULL CHECK (max_attempts > 0),
+    -- At most one job with a given key is queued at a time.
+    unique_key TEXT UNIQUE,
+    -- Recurring jobs queue their next run once done.
+    schedule TEXT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX background_jobs_queue_idx ON background_jobs (priority DESC, run_at);
+-- Deliveries are looked up by issue, to track the progress of each of them.
+CREATE INDEX background_jobs_newsletter_issue_id_idx
+    ON background_jobs ((payload ->> 'newsletter_issue_id'));
+
+INSERT INTO background_jobs (
+    job_id, kind, payload, priority, run_at, max_attempts, unique_key, created_at
+)
+SELECT
+    gen_random_uuid(),
+    'deliver_issue',
+    jsonb_build_object(
+        'newsletter_issue_id', newsletter_issue_id,
+        'subscriber_email', subscriber_email
+    ),
+    0,
+    now(),
+    3,
+    'deliver_issue:' || newsletter_issue_id || ':' || subscriber_email,
+    now()
+FROM issue_delivery_queue;
+DROP TABLE issue_delivery_queue;
+
+INSERT INTO background_jobs (
+    job_id, kind, payload, priority, run_at, n_attempts, max_attempts, created_at
+)
+SELECT
+    email_outbox_id,
+    'subscriber_email',
+    jsonb_build_object('subscriber_id', subscriber_id, 'email', kind),
+    10,
+    execute_after,
+    n_retries,
+    5,
+    created_at
+FROM email_outbox;
+DROP TABLE email_outbox;
@@ -0,0 +1,7 @@
+-- Transactional jobs, that people are waiting for, have workers of their own.
+ALTER TABLE background_jobs
+    ADD COLUMN lane TEXT NOT NULL DEFAULT 'bulk' CHECK (lane IN ('transactional', 'bulk'));
+UPDATE background_jobs
+SET lane = 'transactional', priority = 0
+WHERE kind IN ('subscriber_email', 'send_pending_subscription_reminders');
+ALTER TABLE background_jobs ALTER COLUMN lane DROP DEFAULT;
@@ -0,0 +1,3 @@
+-- Workers dequeue one lane at a time, in order of priority.
+DROP INDEX background_jobs_queue_idx;
+CREATE INDEX background_jobs_queue_idx ON background_jobs (lane, priority DESC, run_at);
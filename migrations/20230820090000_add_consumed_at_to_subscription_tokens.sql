@@ -0,0 +1,3 @@
+-- Used tokens are kept, marked as consumed: a link opened again, for instance
+-- by a link scanner first, still shows whether the subscription is confirmed.
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at timestamptz NULL;
@@ -0,0 +1,5 @@
+-- Issues outlive the account of their author.
+ALTER TABLE newsletter_issues
+    DROP CONSTRAINT newsletter_issues_author_user_id_fkey,
+    ADD CONSTRAINT newsletter_issues_author_user_id_fkey
+        FOREIGN KEY (author_user_id) REFERENCES users (user_id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- When confirmed subscribers were last told they are already subscribed, for
+-- re-submitting the form not to flood their inbox.
+ALTER TABLE subscriptions ADD COLUMN already_subscribed_email_sent_at TIMESTAMPTZ NULL;
@@ -0,0 +1,6 @@
+-- The tokens backfilled when unsubscribe tokens were introduced came from
+-- `random()`, which can be predicted. They are the only ones made of 25
+-- lowercase hex digits: replaced with tokens from a secure generator.
+UPDATE subscriptions
+SET unsubscribe_token = replace(gen_random_uuid()::text, '-', '')
+WHERE unsubscribe_token ~ '^[0-9a-f]{25}$';
@@ -0,0 +1,221 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Prefix of every personal API token, to make them easy to spot in
+/// configuration files and secret scanners.
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// What a personal API token is allowed to do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ApiTokenScope {
+    ReadIssues,
+    PublishIssues,
+}
+
+impl ApiTokenScope {
+    pub const ALL: [ApiTokenScope; 2] = [ApiTokenScope::ReadIssues, ApiTokenScope::PublishIssues];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::ReadIssues => "issues:read",
+            ApiTokenScope::PublishIssues => "issues:write",
+        }
+    }
+}
+
+impl TryFrom<String> for ApiTokenScope {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "issues:read" => Ok(Self::ReadIssues),
+            "issues:write" => Ok(Self::PublishIssues),
+            other => anyhow::bail!("{} is not a known API token scope", other),
+        }
+    }
+}
+
+/// The scopes granted to the API token used to authenticate the current request.
+#[derive(Clone, Debug)]
+pub struct ApiTokenScopes(pub Vec<ApiTokenScope>);
+
+impl ApiTokenScopes {
+    pub fn contains(&self, scope: ApiTokenScope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+fn generate_api_token() -> Secret<String> {
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{}{}", TOKEN_PREFIX, token))
+}
+
+fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Create a new token for `user_id`.
+///
+/// The clear-text token is returned to be shown once to the user:
+/// only its hash is stored.
+#[tracing::instrument(name = "Create API token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiTokenScope],
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+VALUES ($1, $2, $3, $4, $5, now())
+"#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(token.expose_secret()),
+        &scopes,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to store a new API token.")?;
+
+    Ok(token)
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+SELECT token_id, name, scopes, created_at, last_used_at
+FROM api_tokens
+WHERE
+    user_id = $1 AND
+    revoked_at IS NULL
+ORDER BY created_at DESC
+"#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve API tokens.")?;
+
+    Ok(tokens)
+}
+
+/// Returns `false` if the user had no such active token.
+#[tracing::instrument(name = "Revoke API token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+UPDATE api_tokens
+SET revoked_at = now()
+WHERE
+    token_id = $1 AND
+    user_id = $2 AND
+    revoked_at IS NULL
+"#,
+        token_id,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to revoke an API token.")?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Look up the user and scopes associated with an active token.
+#[tracing::instrument(name = "Validate API token", skip(pool, token))]
+pub async fn validate_api_token(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<(Uuid, ApiTokenScopes)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+UPDATE api_tokens
+SET last_used_at = now()
+WHERE
+    token_hash = $1 AND
+    revoked_at IS NULL
+RETURNING user_id, scopes
+"#,
+        hash_api_token(token.expose_secret()),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API token.")?;
+
+    match row {
+        Some(r) => {
+            let scopes = r
+                .scopes
+                .into_iter()
+                .map(ApiTokenScope::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Some((r.user_id, ApiTokenScopes(scopes))))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::ExposeSecret;
+
+    use super::{generate_api_token, hash_api_token, ApiTokenScope};
+
+    #[test]
+    fn generated_tokens_are_prefixed_and_unique() {
+        let first = generate_api_token();
+        let second = generate_api_token();
+        assert!(first.expose_secret().starts_with("z2p_"));
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+
+    #[test]
+    fn hashes_do_not_contain_the_token() {
+        let token = generate_api_token();
+        let hash = hash_api_token(token.expose_secret());
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(&token.expose_secret()[4..]));
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_string_representation() {
+        for scope in ApiTokenScope::ALL {
+            assert_ok_eq!(ApiTokenScope::try_from(scope.as_str().to_string()), scope);
+        }
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert_err!(ApiTokenScope::try_from("subscribers:delete".to_string()));
+    }
+}
@@ -0,0 +1,231 @@
+use actix_multipart::Multipart;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, PayloadError};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use futures_util::TryStreamExt;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+/// Name of the hidden field carrying the token in admin forms.
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+/// Header that non-form clients can use to provide the token instead.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+/// Longer fields cannot hold a token: they are not read any further.
+const MAX_CSRF_FIELD_BYTES: usize = 64;
+
+/// The anti-CSRF token tied to the current session.
+///
+/// It is inserted into the request extensions by `reject_invalid_csrf_tokens`
+/// so that handlers can embed it in the forms they render.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        Self(
+            std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(char::from)
+                .take(32)
+                .collect(),
+        )
+    }
+
+    /// Compare in constant time, to avoid leaking the token through timings.
+    fn matches(&self, candidate: &str) -> bool {
+        let expected = self.0.as_bytes();
+        let candidate = candidate.as_bytes();
+        expected.len() == candidate.len()
+            && expected
+                .iter()
+                .zip(candidate)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+// Rendered in the hidden `csrf_token` field of every admin form.
+impl std::fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for CsrfToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Set on `multipart/form-data` requests whose token is in the body, for
+/// `verify_multipart_csrf_token` to check it.
+struct PendingMultipartCheck;
+
+/// The only routes whose handler checks the token of `multipart/form-data`
+/// forms itself. Other file uploads must send the `X-CSRF-Token` header.
+const MULTIPART_FORM_ROUTES: [&str; 1] = ["/admin/subscribers/import"];
+
+/// Make sure every session has a CSRF token and reject state-changing
+/// requests that do not echo it back.
+///
+/// File uploads are too large to be buffered here: unless they send the
+/// header, they are only accepted by `MULTIPART_FORM_ROUTES`, whose handler
+/// calls `verify_multipart_csrf_token` before reading the rest of the form.
+///
+/// Must run after `reject_anonymous_users`.
+pub async fn reject_invalid_csrf_tokens(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    let token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => CsrfToken(token),
+        None => {
+            // Sessions opened before CSRF protection was rolled out.
+            let token = CsrfToken::generate();
+            session.insert_csrf_token(token.0.clone()).map_err(e500)?;
+            token
+        }
+    };
+
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        match submitted_csrf_token(&mut req).await? {
+            SubmittedToken::InMultipartBody if MULTIPART_FORM_ROUTES.contains(&req.path()) => {
+                req.extensions_mut().insert(PendingMultipartCheck);
+            }
+            SubmittedToken::Found(submitted) if token.matches(&submitted) => {}
+            SubmittedToken::Found(_)
+            | SubmittedToken::Missing
+            | SubmittedToken::InMultipartBody => {
+                return Ok(req.error_response(invalid_csrf_token()));
+            }
+        }
+    }
+
+    req.extensions_mut().insert(token);
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+fn invalid_csrf_token() -> actix_web::Error {
+    let e = anyhow::anyhow!("Missing or invalid CSRF token");
+    let response = HttpResponse::Forbidden().body("Invalid CSRF token");
+    InternalError::from_response(e, response).into()
+}
+
+enum SubmittedToken {
+    Found(String),
+    Missing,
+    /// To be checked by the handler, see `verify_multipart_csrf_token`.
+    InMultipartBody,
+}
+
+/// Look for the token in the dedicated header first, then in the
+/// url-encoded body.
+///
+/// The body has to be buffered to be inspected: we put it back into the
+/// request afterwards, so that handlers can still extract it.
+async fn submitted_csrf_token(
+    req: &mut ServiceRequest,
+) -> Result<SubmittedToken, actix_web::Error> {
+    if let Some(token) = req.headers().get(CSRF_HEADER).and_then(|h| h.to_str().ok()) {
+        return Ok(SubmittedToken::Found(token.to_owned()));
+    }
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with("multipart/form-data") {
+        return Ok(SubmittedToken::InMultipartBody);
+    }
+    if !content_type.starts_with("application/x-www-form-urlencoded") {
+        return Ok(SubmittedToken::Missing);
+    }
+
+    let body = req.extract::<web::Bytes>().await?;
+    let token = find_csrf_field(&body);
+
+    let stream = futures_util::stream::once(async move { Ok::<_, PayloadError>(body) });
+    req.set_payload(Payload::Stream {
+        payload: Box::pin(stream),
+    });
+
+    Ok(match token {
+        Some(token) => SubmittedToken::Found(token),
+        None => SubmittedToken::Missing,
+    })
+}
+
+/// Check the token of a `multipart/form-data` form, carried by its first
+/// field: nothing else is read from forged requests. A no-op for requests
+/// that sent the token in the `X-CSRF-Token` header instead.
+pub async fn verify_multipart_csrf_token(
+    request: &HttpRequest,
+    payload: &mut Multipart,
+) -> Result<(), actix_web::Error> {
+    if request
+        .extensions()
+        .get::<PendingMultipartCheck>()
+        .is_none()
+    {
+        return Ok(());
+    }
+    let token = request
+        .extensions()
+        .get::<CsrfToken>()
+        .cloned()
+        .ok_or_else(|| e500("The CSRF token is not set for the request"))?;
+    // Until the token is read, malformed bodies are as good as forged.
+    let mut field = match payload.try_next().await {
+        Ok(Some(field)) if field.name() == Some(CSRF_FORM_FIELD) => field,
+        _ => return Err(invalid_csrf_token()),
+    };
+    let mut submitted = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(|_| invalid_csrf_token())? {
+        if submitted.len() + chunk.len() > MAX_CSRF_FIELD_BYTES {
+            return Err(invalid_csrf_token());
+        }
+        submitted.extend_from_slice(&chunk);
+    }
+    match std::str::from_utf8(&submitted) {
+        Ok(submitted) if token.matches(submitted) => Ok(()),
+        _ => Err(invalid_csrf_token()),
+    }
+}
+
+fn find_csrf_field(urlencoded: &[u8]) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(urlencoded)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == CSRF_FORM_FIELD)
+                .map(|(_, value)| value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsrfToken;
+
+    #[test]
+    fn a_token_matches_itself_only() {
+        let token = CsrfToken::generate();
+        let other = CsrfToken::generate();
+        assert!(token.matches(token.as_ref()));
+        assert!(!token.matches(other.as_ref()));
+        assert!(!token.matches(""));
+        assert!(!token.matches(&token.as_ref()[1..]));
+    }
+}
@@ -1,12 +1,19 @@
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
 use actix_web::FromRequest;
 use actix_web::HttpMessage;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
 use actix_web_lab::middleware::Next;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
+use crate::authentication::{touch_user_session, validate_api_token, SessionStatus};
+use crate::configuration::SessionSettings;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 
@@ -29,21 +36,101 @@ impl Deref for UserId {
 
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
-    next: Next<impl MessageBody>,
+    next: Next<impl MessageBody + 'static>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = session.get_user_id().map_err(e500)?;
+    let session_id = session.get_session_id().map_err(e500)?;
+
+    match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| e500("The database pool is not registered as app data"))?;
+            let settings = req
+                .app_data::<web::Data<SessionSettings>>()
+                .ok_or_else(|| e500("The session settings are not registered as app data"))?;
+
+            let status = touch_user_session(pool, user_id, session_id, settings)
+                .await
+                .map_err(e500)?;
+            let message = match status {
+                SessionStatus::Active => {
+                    req.extensions_mut().insert(UserId(user_id));
+                    return Ok(next.call(req).await?.map_into_boxed_body());
+                }
+                SessionStatus::Revoked => None,
+                SessionStatus::IdleTimeout => Some(format!(
+                    "Your session expired after {} minutes of inactivity. Please log in again.",
+                    settings.idle_timeout_minutes
+                )),
+                SessionStatus::AbsoluteTimeout => Some(format!(
+                    "Your session expired after {} hours. Please log in again.",
+                    settings.absolute_timeout_hours
+                )),
+            };
+
+            // The session is no longer valid: we purge it and return a regular
+            // response, rather than an error, so that the session and flash
+            // message middlewares get a chance to persist their changes.
+            session.logout();
+            if let Some(message) = message {
+                FlashMessage::info(message).send();
+            }
+            Ok(req.into_response(see_other("/login")).map_into_boxed_body())
+        }
+        _ => {
+            let e = anyhow::anyhow!("The user has not logged in");
+            Ok(req.error_response(InternalError::from_response(e, see_other("/login"))))
+        }
+    }
+}
+
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = match bearer_token(&req) {
+        Some(token) => token,
+        None => {
+            let e = anyhow::anyhow!("Missing or malformed `Authorization: Bearer` header");
+            return Ok(req.error_response(InternalError::from_response(e, unauthorized())));
+        }
+    };
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered as app data"))?;
+
+    match validate_api_token(pool, &token).await.map_err(e500)? {
+        Some((user_id, scopes)) => {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            req.extensions_mut().insert(scopes);
+            Ok(next.call(req).await?.map_into_boxed_body())
         }
         None => {
-            let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, see_other("/login")).into())
+            let e = anyhow::anyhow!("Unknown or revoked API token");
+            Ok(req.error_response(InternalError::from_response(e, unauthorized())))
         }
     }
 }
+
+fn bearer_token(req: &ServiceRequest) -> Option<Secret<String>> {
+    let header_value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header_value.strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(Secret::new(token.to_owned()))
+    }
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="publish""#))
+        .json(serde_json::json!({ "error": "Invalid API token" }))
+}
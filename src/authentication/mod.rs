@@ -1,9 +1,21 @@
-pub use middleware::reject_anonymous_users;
+pub use api_tokens::{
+    create_api_token, list_api_tokens, revoke_api_token, validate_api_token, ApiToken,
+    ApiTokenScope, ApiTokenScopes,
+};
+pub use csrf::{reject_invalid_csrf_tokens, verify_multipart_csrf_token, CsrfToken};
 pub use middleware::UserId;
+pub use middleware::{reject_anonymous_users, reject_invalid_api_tokens};
 pub use password::{
     get_stored_password_hash, update_password, validate_credentials, verify_password_hash,
     AuthError, Credentials,
 };
+pub use sessions::{
+    create_user_session, list_user_sessions, revoke_all_user_sessions, revoke_other_user_sessions,
+    revoke_user_session, touch_user_session, SessionStatus, UserSession,
+};
 
+mod api_tokens;
+mod csrf;
 mod middleware;
 mod password;
+mod sessions;
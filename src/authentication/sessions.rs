@@ -0,0 +1,271 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::SessionSettings;
+
+/// Metadata kept in Postgres for every session opened by a user.
+///
+/// Redis only holds the session state itself: this table lets users see
+/// where they are logged in and revoke sessions remotely.
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Sessions of the user that have expired are deleted on the way, for them
+/// not to pile up.
+#[tracing::instrument(name = "Create user session", skip(pool, user_agent, settings))]
+pub async fn create_user_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    settings: &SessionSettings,
+) -> Result<Uuid, anyhow::Error> {
+    delete_expired_user_sessions(pool, user_id, settings).await?;
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO user_sessions (
+    session_id,
+    user_id,
+    created_at,
+    last_seen_at,
+    ip_address,
+    user_agent
+)
+VALUES ($1, $2, now(), now(), $3, $4)
+"#,
+        session_id,
+        user_id,
+        ip_address,
+        user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to store a new user session.")?;
+
+    Ok(session_id)
+}
+
+/// Outcome of checking a session against the revocation list and the
+/// session lifetime policy.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SessionStatus {
+    Active,
+    Revoked,
+    IdleTimeout,
+    AbsoluteTimeout,
+}
+
+/// Check that a session is still usable and refresh its `last_seen_at` timestamp.
+///
+/// Expired sessions are deleted, so they show up as revoked from then on.
+#[tracing::instrument(name = "Touch user session", skip(pool, settings))]
+pub async fn touch_user_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<SessionStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT created_at, last_seen_at
+FROM user_sessions
+WHERE
+    session_id = $1 AND
+    user_id = $2
+"#,
+        session_id,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user session.")?;
+
+    let status = match row {
+        None => return Ok(SessionStatus::Revoked),
+        Some(r) => session_status(r.created_at, r.last_seen_at, Utc::now(), settings),
+    };
+
+    if status == SessionStatus::Active {
+        sqlx::query!(
+            r#"UPDATE user_sessions SET last_seen_at = now() WHERE session_id = $1"#,
+            session_id,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to perform a query to refresh a user session.")?;
+    } else {
+        revoke_user_session(pool, user_id, session_id).await?;
+    }
+
+    Ok(status)
+}
+
+fn session_status(
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    settings: &SessionSettings,
+) -> SessionStatus {
+    if now - created_at > settings.absolute_timeout() {
+        SessionStatus::AbsoluteTimeout
+    } else if now - last_seen_at > settings.idle_timeout() {
+        SessionStatus::IdleTimeout
+    } else {
+        SessionStatus::Active
+    }
+}
+
+/// Only the sessions that are still usable: expired ones are deleted first.
+#[tracing::instrument(name = "List user sessions", skip(pool, settings))]
+pub async fn list_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    delete_expired_user_sessions(pool, user_id, settings).await?;
+    let sessions = sqlx::query_as!(
+        UserSession,
+        r#"
+SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+FROM user_sessions
+WHERE user_id = $1
+ORDER BY last_seen_at DESC
+"#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve user sessions.")?;
+
+    Ok(sessions)
+}
+
+/// Sessions that `touch_user_session` would find expired, including the ones
+/// of browsers that were closed and never came back.
+#[tracing::instrument(name = "Delete expired user sessions", skip(pool, settings))]
+async fn delete_expired_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+DELETE FROM user_sessions
+WHERE
+    user_id = $1 AND
+    (created_at < $2 OR last_seen_at < $3)
+"#,
+        user_id,
+        now - settings.absolute_timeout(),
+        now - settings.idle_timeout(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to delete expired user sessions.")?;
+
+    Ok(())
+}
+
+/// Returns `false` if the user had no such session.
+#[tracing::instrument(name = "Revoke user session", skip(pool))]
+pub async fn revoke_user_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+DELETE FROM user_sessions
+WHERE
+    session_id = $1 AND
+    user_id = $2
+"#,
+        session_id,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to revoke a user session.")?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+#[tracing::instrument(name = "Revoke other user sessions", skip(pool))]
+pub async fn revoke_other_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+DELETE FROM user_sessions
+WHERE
+    user_id = $1 AND
+    session_id <> $2
+"#,
+        user_id,
+        current_session_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to perform a query to revoke other user sessions.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Revoke all user sessions", skip(pool))]
+pub async fn revoke_all_user_sessions(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(r#"DELETE FROM user_sessions WHERE user_id = $1"#, user_id,)
+        .execute(pool)
+        .await
+        .context("Failed to perform a query to revoke all user sessions.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{session_status, SessionStatus};
+    use crate::configuration::SessionSettings;
+
+    fn settings() -> SessionSettings {
+        SessionSettings {
+            idle_timeout_minutes: 30,
+            absolute_timeout_hours: 12,
+        }
+    }
+
+    #[test]
+    fn a_recently_used_session_is_active() {
+        let now = Utc::now();
+        let status = session_status(now - Duration::hours(1), now, now, &settings());
+        assert_eq!(status, SessionStatus::Active);
+    }
+
+    #[test]
+    fn a_session_unused_for_longer_than_the_idle_timeout_expires() {
+        let now = Utc::now();
+        let last_seen_at = now - Duration::minutes(31);
+        let status = session_status(last_seen_at, last_seen_at, now, &settings());
+        assert_eq!(status, SessionStatus::IdleTimeout);
+    }
+
+    #[test]
+    fn a_session_older_than_the_absolute_timeout_expires_even_if_active() {
+        let now = Utc::now();
+        let status = session_status(now - Duration::hours(13), now, now, &settings());
+        assert_eq!(status, SessionStatus::AbsoluteTimeout);
+    }
+}
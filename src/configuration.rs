@@ -1,5 +1,7 @@
 //! src/configuration.rs
 
+use std::net::IpAddr;
+
 use actix_web::Result;
 use config::Config;
 use secrecy::{ExposeSecret, Secret};
@@ -9,7 +11,12 @@ use sqlx::{
     ConnectOptions,
 };
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    email_template::{EmailTemplates, TemplateError},
+    jobs::{Lane, Schedule},
+};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -17,6 +24,23 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub session: SessionSettings,
+    pub security_headers: SecurityHeadersSettings,
+    #[serde(default)]
+    pub subscription_pages: SubscriptionPagesSettings,
+    /// Sent once a subscription is confirmed, if set.
+    #[serde(default)]
+    pub welcome_email: Option<WelcomeEmailSettings>,
+    pub pending_subscriptions: PendingSubscriptionsSettings,
+    pub jobs: JobsSettings,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SessionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub absolute_timeout_hours: u64,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -25,6 +49,10 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// Postmark message streams of each lane of the background worker.
+    /// The default stream of the server is used when unset.
+    pub transactional_message_stream: Option<String>,
+    pub bulk_message_stream: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -34,6 +62,11 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub privacy_policy_version: String,
+    /// Reverse proxies whose `Forwarded` and `X-Forwarded-For` headers are
+    /// trusted to tell the IP address of clients.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -48,15 +81,21 @@ pub struct DatabaseSettings {
 }
 
 impl EmailClientSettings {
-    pub fn client(self) -> EmailClient {
+    /// A client sending through the message stream of `lane`.
+    pub fn client(self, lane: Lane) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
+        let message_stream = match lane {
+            Lane::Transactional => self.transactional_message_stream,
+            Lane::Bulk => self.bulk_message_stream,
+        };
         EmailClient::new(
             self.base_url,
             sender_email,
             self.authorization_token,
             timeout,
         )
+        .with_message_stream(message_stream)
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -68,6 +107,86 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SecurityHeadersSettings {
+    pub content_security_policy: String,
+    /// Only report violations to `/csp_report` instead of blocking them.
+    pub csp_report_only: bool,
+    pub frame_options: String,
+    pub referrer_policy: String,
+    /// `Strict-Transport-Security` is only sent when set.
+    pub hsts_max_age_seconds: Option<u64>,
+}
+
+/// Where the pages of the subscription flow are hosted, for instance on a
+/// marketing site. The application renders its own when left unset.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct SubscriptionPagesSettings {
+    pub check_inbox_url: Option<String>,
+    pub confirmed_url: Option<String>,
+    pub link_expired_url: Option<String>,
+    pub invalid_link_url: Option<String>,
+}
+
+/// Email templates, with the same merge fields as newsletter issues.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct WelcomeEmailSettings {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl WelcomeEmailSettings {
+    pub fn templates(&self) -> Result<EmailTemplates, TemplateError> {
+        EmailTemplates::parse(&self.subject, &self.html_body, &self.text_body)
+    }
+}
+
+/// What happens to subscriptions that are never confirmed.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct PendingSubscriptionsSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub reminder_delay_hours: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_days: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub check_interval_minutes: u64,
+    pub purge_schedule: Schedule,
+}
+
+/// How many jobs of each lane the background worker runs at the same time.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct JobsSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub transactional_concurrency: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub bulk_concurrency: usize,
+}
+
+impl PendingSubscriptionsSettings {
+    pub fn reminder_delay(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.reminder_delay_hours as i64)
+    }
+
+    pub fn retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.retention_days as i64)
+    }
+
+    pub fn check_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.check_interval_minutes * 60)
+    }
+}
+
+impl SessionSettings {
+    pub fn idle_timeout(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.idle_timeout_minutes as i64)
+    }
+
+    pub fn absolute_timeout(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.absolute_timeout_hours as i64)
+    }
+}
+
 impl DatabaseSettings {
     pub fn without_db(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
@@ -0,0 +1,145 @@
+//! Consent records: every opt-in and opt-out, along with how it was made.
+//!
+//! `subscriptions.status` only holds the latest state: these events are kept
+//! to prove that a subscriber consented to receive the newsletter.
+use actix_web::{http::header::USER_AGENT, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::utils::client_ip;
+
+/// Submitted sources longer than this are truncated.
+const MAX_SOURCE_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentAction {
+    Subscribed,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl ConsentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscribed => "subscribed",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+/// How an opt-in or opt-out was made.
+#[derive(Debug)]
+pub struct ConsentContext {
+    pub source: String,
+    pub privacy_policy_version: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ConsentContext {
+    pub fn new(request: &HttpRequest, source: &str, privacy_policy_version: &str) -> Self {
+        let ip_address = client_ip(request);
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_owned);
+        Self {
+            source: source.chars().take(MAX_SOURCE_LENGTH).collect(),
+            privacy_policy_version: privacy_policy_version.to_owned(),
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEvent {
+    pub action: String,
+    pub source: String,
+    pub privacy_policy_version: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub occurred_at: DateTime<Utc>,
+}
+
+fn serialize_rfc3339<S: serde::Serializer>(
+    date: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&date.to_rfc3339())
+}
+
+/// Record `action` for the subscribers in `subscriber_ids`, with their current email.
+#[tracing::instrument(name = "Record consent events", skip(executor, context))]
+pub async fn record_consent_events(
+    executor: impl PgExecutor<'_>,
+    subscriber_ids: &[Uuid],
+    action: ConsentAction,
+    context: &ConsentContext,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO consent_events (
+    consent_event_id,
+    subscriber_id,
+    email,
+    action,
+    source,
+    privacy_policy_version,
+    ip_address,
+    user_agent,
+    occurred_at
+)
+SELECT gen_random_uuid(), id, email, $2, $3, $4, $5, $6, now()
+FROM subscriptions
+WHERE id = ANY($1)
+"#,
+        subscriber_ids,
+        action.as_str(),
+        context.source,
+        context.privacy_policy_version,
+        context.ip_address,
+        context.user_agent,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record consent events")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Record a consent event", skip(executor, context))]
+pub async fn record_consent_event(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    action: ConsentAction,
+    context: &ConsentContext,
+) -> Result<(), anyhow::Error> {
+    record_consent_events(executor, &[subscriber_id], action, context).await
+}
+
+/// The consent history of a subscriber, oldest first.
+#[tracing::instrument(name = "List consent events", skip(pool))]
+pub async fn list_consent_events(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        ConsentEvent,
+        r#"
+SELECT action, source, privacy_policy_version, ip_address, user_agent, occurred_at
+FROM consent_events
+WHERE subscriber_id = $1
+ORDER BY occurred_at
+"#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list consent events")?;
+    Ok(events)
+}
@@ -0,0 +1,486 @@
+//! Requests from data subjects: people asking for a copy of everything we
+//! store about their email address, or for it to be erased.
+//!
+//! Emails are matched regardless of their case. Erased addresses leave a
+//! tombstone behind, a keyed digest of the address, so that they are not
+//! imported again by mistake.
+use std::collections::HashSet;
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{
+    consent::ConsentEvent,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    issue_delivery_worker::DeliverIssue,
+    jobs::{enqueue, Job, JobContext, Lane},
+};
+
+/// How long the links emailed to data subjects remain valid.
+pub const DATA_REQUEST_TTL_HOURS: i64 = 24;
+/// Links emailed to an address over `DATA_REQUEST_RATE_LIMIT_WINDOW_MINUTES`.
+const MAX_DATA_REQUESTS: i64 = 3;
+const DATA_REQUEST_RATE_LIMIT_WINDOW_MINUTES: i64 = 60;
+
+/// Everything stored about an email address.
+#[derive(serde::Serialize)]
+pub struct SubjectData {
+    pub email: String,
+    pub subscription: Option<StoredSubscription>,
+    pub subscription_tokens: Vec<StoredToken>,
+    pub pending_deliveries: Vec<PendingDelivery>,
+    pub delivery_log: Vec<LoggedDelivery>,
+    pub consent_events: Vec<ConsentEvent>,
+}
+
+#[derive(serde::Serialize)]
+pub struct StoredSubscription {
+    id: String,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: String,
+}
+
+/// Only the digest of confirmation tokens is stored.
+#[derive(serde::Serialize)]
+pub struct StoredToken {
+    created_at: String,
+    expires_at: String,
+    consumed_at: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PendingDelivery {
+    newsletter_issue_id: String,
+    issue_title: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct LoggedDelivery {
+    newsletter_issue_id: String,
+    issue_title: String,
+    outcome: String,
+    attempted_at: String,
+}
+
+impl SubjectData {
+    pub fn is_empty(&self) -> bool {
+        self.subscription.is_none()
+            && self.pending_deliveries.is_empty()
+            && self.delivery_log.is_empty()
+            && self.consent_events.is_empty()
+    }
+}
+
+#[tracing::instrument(name = "Collect the data stored about an email", skip(pool))]
+pub async fn collect_subject_data(
+    pool: &PgPool,
+    email: &str,
+) -> Result<SubjectData, anyhow::Error> {
+    let subscription = sqlx::query!(
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE lower(email) = lower($1)
+"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscription")?
+    .map(|r| StoredSubscription {
+        id: r.id.to_string(),
+        email: r.email,
+        name: r.name,
+        status: r.status,
+        subscribed_at: r.subscribed_at.to_rfc3339(),
+    });
+    let subscription_tokens = sqlx::query!(
+        r#"
+SELECT t.created_at, t.expires_at, t.consumed_at
+FROM subscription_tokens t
+JOIN subscriptions s ON s.id = t.subscriber_id
+WHERE lower(s.email) = lower($1)
+"#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscription tokens")?
+    .into_iter()
+    .map(|r| StoredToken {
+        created_at: r.created_at.to_rfc3339(),
+        expires_at: r.expires_at.to_rfc3339(),
+        consumed_at: r.consumed_at.map(|t| t.to_rfc3339()),
+    })
+    .collect();
+    let pending_deliveries = sqlx::query!(
+        r#"
+SELECT i.newsletter_issue_id, i.title
+FROM background_jobs j
+JOIN newsletter_issues i ON i.newsletter_issue_id::text = j.payload ->> 'newsletter_issue_id'
+WHERE j.kind = $2 AND lower(j.payload ->> 'subscriber_email') = lower($1)
+"#,
+        email,
+        DeliverIssue::KIND,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending deliveries")?
+    .into_iter()
+    .map(|r| PendingDelivery {
+        newsletter_issue_id: r.newsletter_issue_id.to_string(),
+        issue_title: r.title,
+    })
+    .collect();
+    let delivery_log = sqlx::query!(
+        r#"
+SELECT l.newsletter_issue_id, i.title, l.outcome, l.attempted_at
+FROM issue_delivery_log l
+JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+WHERE lower(l.subscriber_email) = lower($1)
+ORDER BY l.attempted_at
+"#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery log")?
+    .into_iter()
+    .map(|r| LoggedDelivery {
+        newsletter_issue_id: r.newsletter_issue_id.to_string(),
+        issue_title: r.title,
+        outcome: r.outcome,
+        attempted_at: r.attempted_at.to_rfc3339(),
+    })
+    .collect();
+    let consent_events = sqlx::query_as!(
+        ConsentEvent,
+        r#"
+SELECT action, source, privacy_policy_version, ip_address, user_agent, occurred_at
+FROM consent_events
+WHERE lower(email) = lower($1)
+ORDER BY occurred_at
+"#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the consent events")?;
+
+    Ok(SubjectData {
+        email: email.to_owned(),
+        subscription,
+        subscription_tokens,
+        pending_deliveries,
+        delivery_log,
+        consent_events,
+    })
+}
+
+/// Delete everything stored about `email`, and leave a tombstone in its place.
+#[tracing::instrument(
+    name = "Erase the data stored about an email",
+    skip(transaction, hmac_secret)
+)]
+pub async fn erase_subject(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+DELETE FROM subscription_tokens
+WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))
+"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscription tokens")?;
+    // A delivery in progress holds a lock on its job, and logs its outcome
+    // before releasing it: the log must be cleaned up after the queue.
+    sqlx::query!(
+        r#"
+DELETE FROM background_jobs
+WHERE kind = $2 AND lower(payload ->> 'subscriber_email') = lower($1)
+"#,
+        email,
+        DeliverIssue::KIND,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the pending deliveries")?;
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_log WHERE lower(subscriber_email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the delivery log")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscription")?;
+    sqlx::query!(
+        r#"DELETE FROM data_request_tokens WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the data requests")?;
+    sqlx::query!(
+        r#"DELETE FROM background_jobs WHERE kind = $2 AND lower(payload ->> 'email') = lower($1)"#,
+        email,
+        DataRequestLink::KIND,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the queued data request links")?;
+    // `consent_events` is append-only, except for erasures.
+    sqlx::query!(r#"SELECT set_config('zero2prod.erasing', 'on', true)"#)
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to allow the deletion of consent events")?;
+    sqlx::query!(
+        r#"DELETE FROM consent_events WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the consent events")?;
+    sqlx::query!(
+        r#"
+INSERT INTO erased_subscribers (email_digest, erased_at)
+VALUES ($1, now())
+ON CONFLICT (email_digest) DO UPDATE SET erased_at = EXCLUDED.erased_at
+"#,
+        erased_email_digest(email, hmac_secret)
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the tombstone")?;
+    Ok(())
+}
+
+/// The lowercased emails among `emails` that have been erased on request.
+#[tracing::instrument(skip_all)]
+pub async fn erased_emails(
+    pool: &PgPool,
+    emails: &[String],
+    hmac_secret: &Secret<String>,
+) -> Result<HashSet<String>, anyhow::Error> {
+    let digests: Vec<String> = emails
+        .iter()
+        .map(|e| erased_email_digest(e, hmac_secret))
+        .collect();
+    let erased: HashSet<String> = sqlx::query!(
+        r#"SELECT email_digest FROM erased_subscribers WHERE email_digest = ANY($1)"#,
+        &digests[..]
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to look for erased subscribers")?
+    .into_iter()
+    .map(|r| r.email_digest)
+    .collect();
+    Ok(emails
+        .iter()
+        .zip(digests)
+        .filter(|(_, digest)| erased.contains(digest))
+        .map(|(email, _)| email.to_lowercase())
+        .collect())
+}
+
+/// An unkeyed hash of an email could be reversed by hashing a list of
+/// known addresses: the digest is keyed with the application secret.
+fn erased_email_digest(email: &str, hmac_secret: &Secret<String>) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(email.to_lowercase().as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// The link to the data stored about `email`, emailed to that address if
+/// there is any data.
+///
+/// Queued for unknown addresses as well: requests take as long either way,
+/// and do not tell whether an address is known.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DataRequestLink {
+    pub email: String,
+}
+
+impl Job for DataRequestLink {
+    const KIND: &'static str = "data_request_link";
+    const LANE: Lane = Lane::Transactional;
+    const MAX_ATTEMPTS: i16 = 5;
+
+    fn unique_key(&self) -> Option<String> {
+        Some(format!("{}:{}", Self::KIND, self.email.to_lowercase()))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn run(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        let data = collect_subject_data(&context.pool, &self.email).await?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        let token = create_data_request(transaction, &self.email).await?;
+        let email = SubscriberEmail::parse(self.email).map_err(anyhow::Error::msg)?;
+        send_data_request_email(
+            context.email_client(Self::LANE),
+            &email,
+            &context.base_url,
+            &token,
+        )
+        .await
+        .context("Failed to send a data request email")
+    }
+}
+
+/// Queue a `DataRequestLink` for `email`, unless it was sent too many of them
+/// lately. Returns `false` if it was not queued.
+#[tracing::instrument(name = "Request the data of a subject", skip(transaction))]
+pub async fn request_subject_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<bool, anyhow::Error> {
+    let n_recent_requests = sqlx::query!(
+        r#"
+SELECT count(*) AS "count!"
+FROM data_request_tokens
+WHERE lower(email) = lower($1) AND created_at > $2
+"#,
+        email,
+        Utc::now() - Duration::minutes(DATA_REQUEST_RATE_LIMIT_WINDOW_MINUTES),
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to count the recent data requests")?
+    .count;
+    if n_recent_requests >= MAX_DATA_REQUESTS {
+        tracing::warn!("Too many data requests");
+        return Ok(false);
+    }
+    // Only one link is queued at a time for an address.
+    enqueue(
+        transaction,
+        &DataRequestLink {
+            email: email.to_owned(),
+        },
+    )
+    .await
+}
+
+#[tracing::instrument(skip(email_client, base_url, token))]
+async fn send_data_request_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let link = format!("{}/privacy/request?token={}", base_url, token);
+    let plain_body = format!(
+        "Someone, hopefully you, asked for the data we store about this address.\n\
+        Visit {} to download or erase it. The link expires in {} hours.\n\
+        If you did not ask for it, you can ignore this email.",
+        link, DATA_REQUEST_TTL_HOURS
+    );
+    let html_body = format!(
+        "Someone, hopefully you, asked for the data we store about this address.<br />\
+        Click <a href=\"{}\">here</a> to download or erase it. The link expires in {} hours.<br />\
+        If you did not ask for it, you can ignore this email.",
+        link, DATA_REQUEST_TTL_HOURS
+    );
+    email_client
+        .send_email(email, "Your personal data", &html_body, &plain_body)
+        .await?;
+    Ok(())
+}
+
+/// Create the token of the link emailed to someone asking for their data.
+#[tracing::instrument(name = "Create a data request", skip(transaction))]
+async fn create_data_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<String, anyhow::Error> {
+    let token = generate_data_request_token();
+    sqlx::query!(
+        r#"INSERT INTO data_request_tokens (token_hash, email, created_at) VALUES ($1, $2, now())"#,
+        hash_data_request_token(&token),
+        email
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to store the data request token")?;
+    Ok(token)
+}
+
+/// The email a data request was made for, unless the token is unknown or expired.
+#[tracing::instrument(name = "Validate a data request", skip_all)]
+pub async fn data_request_email(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email FROM data_request_tokens WHERE token_hash = $1 AND created_at > $2"#,
+        hash_data_request_token(token),
+        Utc::now() - Duration::hours(DATA_REQUEST_TTL_HOURS),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the data request")?;
+    Ok(row.map(|r| r.email))
+}
+
+fn generate_data_request_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect()
+}
+
+fn hash_data_request_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::erased_email_digest;
+
+    #[test]
+    fn tombstones_match_emails_regardless_of_their_case() {
+        let secret = Secret::new("secret".to_string());
+        assert_eq!(
+            erased_email_digest("ursula@example.com", &secret),
+            erased_email_digest("Ursula@Example.com", &secret)
+        );
+    }
+
+    #[test]
+    fn tombstones_depend_on_the_secret() {
+        let email = "ursula@example.com";
+        assert_ne!(
+            erased_email_digest(email, &Secret::new("secret".to_string())),
+            erased_email_digest(email, &Secret::new("another secret".to_string()))
+        );
+    }
+}
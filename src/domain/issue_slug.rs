@@ -0,0 +1,72 @@
+use uuid::Uuid;
+
+const MAX_TITLE_LENGTH: usize = 60;
+
+/// The URL-friendly identifier of an issue in the public archive.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl IssueSlug {
+    /// Build a slug out of the issue title, made unique by a prefix of its id:
+    /// several issues can share the same title.
+    pub fn new(title: &str, newsletter_issue_id: Uuid) -> Self {
+        let words: Vec<String> = title
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect();
+        let mut slug = String::new();
+        for word in words {
+            if slug.chars().count() + word.chars().count() > MAX_TITLE_LENGTH {
+                break;
+            }
+            slug.push_str(&word);
+            slug.push('-');
+        }
+        slug.push_str(&newsletter_issue_id.simple().to_string()[..8]);
+        Self(slug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::IssueSlug;
+
+    fn id() -> Uuid {
+        Uuid::parse_str("3fa85f64-5717-4562-b3fc-2c963f66afa6").unwrap()
+    }
+
+    #[test]
+    fn titles_are_lowercased_and_hyphenated() {
+        let slug = IssueSlug::new("Hello, World! Issue #3", id());
+        assert_eq!(slug.as_ref(), "hello-world-issue-3-3fa85f64");
+    }
+
+    #[test]
+    fn non_ascii_letters_are_kept() {
+        let slug = IssueSlug::new("Été à Paris", id());
+        assert_eq!(slug.as_ref(), "été-à-paris-3fa85f64");
+    }
+
+    #[test]
+    fn titles_without_words_only_keep_the_id() {
+        let slug = IssueSlug::new("!!!", id());
+        assert_eq!(slug.as_ref(), "3fa85f64");
+    }
+
+    #[test]
+    fn long_titles_are_truncated_on_word_boundaries() {
+        let title = "word ".repeat(30);
+        let slug = IssueSlug::new(&title, id());
+        assert!(slug.as_ref().len() <= 60 + 8);
+        assert!(slug.as_ref().ends_with("-word-3fa85f64"));
+    }
+}
@@ -1,9 +1,13 @@
 pub use admin_password::AdminPassword;
+pub use issue_slug::IssueSlug;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::{IllegalTransition, SubscriptionStatus};
 
 mod admin_password;
+mod issue_slug;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
@@ -0,0 +1,163 @@
+/// The state of a subscription, stored in `subscriptions.status`.
+///
+/// New subscriptions are pending until confirmed. Opting out is allowed from
+/// every state, and either pending or confirmed addresses can bounce. Leaving
+/// `unsubscribed` or `bounced` takes a new confirmation, through
+/// `pending_confirmation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// The address does not accept our emails anymore.
+    Bounced,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("A subscription cannot go from `{from}` to `{to}`")]
+pub struct IllegalTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [Self; 4] = [
+        Self::PendingConfirmation,
+        Self::Confirmed,
+        Self::Unsubscribed,
+        Self::Bounced,
+    ];
+
+    pub fn parse(status: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|s| s.as_str() == status)
+            .ok_or_else(|| format!("`{}` is not a valid subscription status", status))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Bounced => "bounced",
+        }
+    }
+
+    pub fn can_become(self, next: Self) -> bool {
+        use SubscriptionStatus::*;
+        match (self, next) {
+            (_, Unsubscribed) => true,
+            // Asking for a new confirmation email.
+            (PendingConfirmation, PendingConfirmation) => true,
+            (PendingConfirmation, Confirmed | Bounced) => true,
+            (Confirmed, Bounced) => true,
+            (Unsubscribed | Bounced, PendingConfirmation) => true,
+            _ => false,
+        }
+    }
+
+    pub fn transition_to(self, next: Self) -> Result<Self, IllegalTransition> {
+        if self.can_become(next) {
+            Ok(next)
+        } else {
+            Err(IllegalTransition {
+                from: self,
+                to: next,
+            })
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use proptest::{collection::vec, prelude::any, proptest, sample::select};
+
+    use super::SubscriptionStatus;
+    use super::SubscriptionStatus::*;
+
+    fn status() -> impl proptest::strategy::Strategy<Value = SubscriptionStatus> {
+        select(SubscriptionStatus::ALL.to_vec())
+    }
+
+    proptest! {
+        #[test]
+        fn statuses_are_parsed_back_from_their_column_value(status in status()) {
+            assert_ok_eq!(SubscriptionStatus::parse(status.as_str()), status);
+        }
+
+        #[test]
+        fn other_values_are_rejected(value in any::<String>()) {
+            if SubscriptionStatus::ALL.iter().all(|s| s.as_str() != value) {
+                assert_err!(SubscriptionStatus::parse(&value));
+            }
+        }
+
+        #[test]
+        fn transitions_only_succeed_when_they_are_allowed(from in status(), to in status()) {
+            match from.transition_to(to) {
+                Ok(next) => {
+                    assert!(from.can_become(to));
+                    assert_eq!(next, to);
+                }
+                Err(e) => {
+                    assert!(!from.can_become(to));
+                    assert_eq!((e.from, e.to), (from, to));
+                }
+            }
+        }
+
+        #[test]
+        fn opting_out_is_always_allowed(from in status()) {
+            assert!(from.can_become(Unsubscribed));
+        }
+
+        #[test]
+        fn only_pending_subscriptions_can_be_confirmed(from in status()) {
+            assert_eq!(from.can_become(Confirmed), from == PendingConfirmation);
+        }
+
+        #[test]
+        fn confirmations_always_follow_a_pending_confirmation(
+            attempts in vec(status(), 0..50)
+        ) {
+            // Every new subscription starts pending.
+            let mut current = PendingConfirmation;
+            for next in attempts {
+                let previous = current;
+                if let Ok(next) = current.transition_to(next) {
+                    current = next;
+                }
+                if current == Confirmed && previous != Confirmed {
+                    assert_eq!(previous, PendingConfirmation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn confirmed_subscriptions_cannot_go_back_to_pending() {
+        assert_err!(Confirmed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn leaving_unsubscribed_or_bounced_requires_a_new_confirmation() {
+        assert_err!(Unsubscribed.transition_to(Confirmed));
+        assert_err!(Bounced.transition_to(Confirmed));
+        assert_ok_eq!(
+            Unsubscribed.transition_to(PendingConfirmation),
+            PendingConfirmation
+        );
+        assert_ok_eq!(
+            Bounced.transition_to(PendingConfirmation),
+            PendingConfirmation
+        );
+    }
+}
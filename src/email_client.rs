@@ -9,6 +9,7 @@ pub struct EmailClient {
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    message_stream: Option<String>,
 }
 
 impl EmailClient {
@@ -25,9 +26,17 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            message_stream: None,
         }
     }
 
+    /// Send through a Postmark message stream, instead of the default one
+    /// of the server.
+    pub fn with_message_stream(mut self, message_stream: Option<String>) -> Self {
+        self.message_stream = message_stream;
+        self
+    }
+
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
@@ -46,6 +55,7 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            message_stream: self.message_stream.as_deref(),
         };
 
         let _builder = self
@@ -72,6 +82,8 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
 }
 
 #[cfg(test)]
@@ -80,7 +92,7 @@ mod tests {
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     use crate::domain::SubscriberEmail;
@@ -152,6 +164,54 @@ mod tests {
         // No need to add an assert, the MockServer panic if any matcher fails
     }
 
+    #[tokio::test]
+    async fn send_email_goes_through_the_message_stream_if_set() {
+        // Given
+        let mock_server = MockServer::start().await;
+        let email_client =
+            email_client(mock_server.uri()).with_message_stream(Some("broadcast".into()));
+
+        Mock::given(body_partial_json(
+            serde_json::json!({ "MessageStream": "broadcast" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // When
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Then
+        claims::assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_leaves_the_message_stream_to_the_server_by_default() {
+        // Given
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // When
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        // Then
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("MessageStream").is_none());
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         // Given
@@ -0,0 +1,158 @@
+//! Processing of the HTML body of newsletter issues before it is stored.
+//!
+//! Email clients ignore `<style>` blocks more often than not and never run
+//! scripts: CSS is inlined into `style` attributes and anything else that
+//! would not survive the trip to an inbox is stripped.
+use std::borrow::Cow;
+
+use anyhow::Context;
+use css_inline::CSSInliner;
+
+/// Gmail clips messages whose HTML is larger than this, hiding the rest
+/// of the issue behind a "View entire message" link.
+pub const GMAIL_CLIPPING_THRESHOLD_BYTES: usize = 102 * 1024;
+
+/// Elements that are removed from issues, and reported to the editor.
+const DISALLOWED_ELEMENTS: [&str; 10] = [
+    "script", "link", "iframe", "object", "embed", "form", "input", "button", "meta", "base",
+];
+
+pub struct ProcessedHtml {
+    pub html: String,
+    /// Things the editor should know before publishing the issue.
+    pub warnings: Vec<String>,
+}
+
+pub fn process_issue_html(html: &str) -> Result<ProcessedHtml, anyhow::Error> {
+    let mut warnings = Vec::new();
+
+    let removed = disallowed_elements(html);
+    if !removed.is_empty() {
+        warnings.push(format!(
+            "These elements are not allowed in emails and have been removed: {}",
+            removed.join(", ")
+        ));
+    }
+
+    let inlined = CSSInliner::options()
+        .load_remote_stylesheets(false)
+        .build()
+        .inline(html)
+        .context("Failed to inline CSS")?;
+    let html = sanitizer().clean(&inlined).to_string();
+
+    if html.len() > GMAIL_CLIPPING_THRESHOLD_BYTES {
+        warnings.push(format!(
+            "The issue is {}KB of HTML: Gmail clips emails larger than {}KB, \
+            subscribers would have to click through to read all of it",
+            html.len() / 1024,
+            GMAIL_CLIPPING_THRESHOLD_BYTES / 1024
+        ));
+    }
+
+    Ok(ProcessedHtml { html, warnings })
+}
+
+fn disallowed_elements(html: &str) -> Vec<&'static str> {
+    let html = html.to_lowercase();
+    DISALLOWED_ELEMENTS
+        .into_iter()
+        .filter(|element| {
+            let opening_tag = format!("<{}", element);
+            html.match_indices(&opening_tag).any(|(i, _)| {
+                matches!(
+                    html[i + opening_tag.len()..].chars().next(),
+                    Some(' ' | '\t' | '\n' | '\r' | '>' | '/')
+                )
+            })
+        })
+        .collect()
+}
+
+fn sanitizer() -> ammonia::Builder<'static> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        // Presentational attributes are still the most reliable way
+        // to lay out an email.
+        .add_generic_attributes(["style", "align", "width", "height", "bgcolor"])
+        .attribute_filter(|_, attribute, value| {
+            if attribute == "style" && !is_safe_css(value) {
+                None
+            } else {
+                Some(Cow::Borrowed(value))
+            }
+        });
+    builder
+}
+
+// Inline styles must not fetch anything (tracking pixels, `javascript:` URLs...).
+fn is_safe_css(css: &str) -> bool {
+    let css = css.to_lowercase();
+    !["url(", "expression(", "@import", "javascript:"]
+        .iter()
+        .any(|forbidden| css.contains(forbidden))
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+
+    use super::{process_issue_html, GMAIL_CLIPPING_THRESHOLD_BYTES};
+
+    #[test]
+    fn style_blocks_are_inlined() {
+        let processed = assert_ok!(process_issue_html(
+            "<style>p { color: red; }</style><p>Hello</p>"
+        ));
+        assert!(processed
+            .html
+            .contains(r#"<p style="color: red;">Hello</p>"#));
+        assert!(!processed.html.contains("<style>"));
+        assert!(processed.warnings.is_empty());
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_stripped() {
+        let processed = assert_ok!(process_issue_html(
+            r#"<script>alert(1)</script><p onclick="alert(1)">Click me</p>"#
+        ));
+        assert!(!processed.html.contains("<script>"));
+        assert!(!processed.html.contains("onclick"));
+        assert!(processed.html.contains("Click me"));
+        assert_eq!(processed.warnings.len(), 1);
+        assert!(processed.warnings[0].contains("script"));
+    }
+
+    #[test]
+    fn external_stylesheets_are_neither_loaded_nor_kept() {
+        let processed = assert_ok!(process_issue_html(
+            r#"<link rel="stylesheet" href="https://example.com/style.css"><p>Hello</p>"#
+        ));
+        assert!(!processed.html.contains("example.com"));
+        assert!(processed.warnings[0].contains("link"));
+    }
+
+    #[test]
+    fn styles_loading_resources_are_dropped() {
+        let processed = assert_ok!(process_issue_html(
+            r#"<p style="background: url(https://tracker.example.com/pixel.gif)">Hello</p>"#
+        ));
+        assert!(!processed.html.contains("tracker"));
+    }
+
+    #[test]
+    fn elements_are_matched_on_their_full_name() {
+        let processed = assert_ok!(process_issue_html(
+            "<p><basefont>Legacy</basefont> markup</p>"
+        ));
+        assert!(processed.warnings.is_empty());
+    }
+
+    #[test]
+    fn large_issues_trigger_a_clipping_warning() {
+        let html = format!("<p>{}</p>", "a".repeat(GMAIL_CLIPPING_THRESHOLD_BYTES));
+        let processed = assert_ok!(process_issue_html(&html));
+        assert_eq!(processed.warnings.len(), 1);
+        assert!(processed.warnings[0].contains("Gmail clips"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Emails to subscribers, queued as jobs in the same transaction as the
+//! change that calls for them.
+//!
+//! Requests do not wait on the email API, and an email is only ever sent for
+//! changes that were committed. Failed attempts are retried with a backoff.
+use anyhow::Context;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    email_template::MergeFields,
+    jobs::{enqueue, Job, JobContext, Lane},
+    routes::{
+        generate_subscription_token, send_already_subscribed_email, send_confirmation_email,
+        store_token, unsubscribe_url,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxEmail {
+    /// With a new confirmation link, generated when the email is sent.
+    Confirmation,
+    /// To people trying to subscribe again with a confirmed address.
+    AlreadySubscribed,
+    /// Once a subscription is confirmed, if a welcome email is configured.
+    Welcome,
+}
+
+impl OutboxEmail {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::AlreadySubscribed => "already_subscribed",
+            Self::Welcome => "welcome",
+        }
+    }
+}
+
+/// An email to a subscriber, as stored in the payload of its job.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct QueuedEmail {
+    pub subscriber_id: Uuid,
+    pub email: OutboxEmail,
+}
+
+impl Job for QueuedEmail {
+    const KIND: &'static str = "subscriber_email";
+    const LANE: Lane = Lane::Transactional;
+    const MAX_ATTEMPTS: i16 = 5;
+
+    #[tracing::instrument(
+        skip_all,
+        fields(subscriber_id = %self.subscriber_id, email = ?self.email)
+    )]
+    async fn run(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        self.send(transaction, context, Self::LANE).await
+    }
+}
+
+/// A confirmation email to an imported subscriber.
+///
+/// Imports queue thousands of them at once: they go through the bulk lane,
+/// so that they do not hold up the emails people are waiting for.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ImportConfirmationEmail {
+    pub subscriber_id: Uuid,
+}
+
+impl Job for ImportConfirmationEmail {
+    const KIND: &'static str = "import_confirmation_email";
+    const LANE: Lane = Lane::Bulk;
+    const MAX_ATTEMPTS: i16 = 5;
+
+    #[tracing::instrument(skip_all, fields(subscriber_id = %self.subscriber_id))]
+    async fn run(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        let email = QueuedEmail {
+            subscriber_id: self.subscriber_id,
+            email: OutboxEmail::Confirmation,
+        };
+        email.send(transaction, context, Self::LANE).await
+    }
+}
+
+impl QueuedEmail {
+    async fn send(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+        lane: Lane,
+    ) -> Result<(), anyhow::Error> {
+        let subscriber = sqlx::query_as!(
+            StoredSubscriber,
+            r#"SELECT email, name, status, unsubscribe_token FROM subscriptions WHERE id = $1"#,
+            self.subscriber_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to retrieve the subscriber")?;
+        match subscriber {
+            Some(subscriber) => {
+                send_outbox_email(transaction, context, lane, self, &subscriber).await
+            }
+            None => {
+                tracing::info!("Skipping an email. The subscriber was deleted since it was queued");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Queue an email, sent once `transaction` is committed.
+#[tracing::instrument(name = "Queue an email to a subscriber", skip(transaction))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: OutboxEmail,
+) -> Result<(), anyhow::Error> {
+    enqueue(
+        transaction,
+        &QueuedEmail {
+            subscriber_id,
+            email,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+struct StoredSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    unsubscribe_token: String,
+}
+
+/// Sent through the message stream of `lane`.
+///
+/// Emails that no longer make sense, or that cannot be sent to the stored
+/// details, are skipped rather than retried.
+async fn send_outbox_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    context: &JobContext,
+    lane: Lane,
+    queued: &QueuedEmail,
+    subscriber: &StoredSubscriber,
+) -> Result<(), anyhow::Error> {
+    let status = SubscriptionStatus::parse(&subscriber.status).map_err(anyhow::Error::msg)?;
+    let expected_status = match queued.email {
+        OutboxEmail::Confirmation => SubscriptionStatus::PendingConfirmation,
+        OutboxEmail::AlreadySubscribed | OutboxEmail::Welcome => SubscriptionStatus::Confirmed,
+    };
+    if status != expected_status {
+        tracing::info!(%status, "Skipping an email. The subscriber has moved on since it was queued");
+        return Ok(());
+    }
+    let new_subscriber = match (
+        SubscriberEmail::parse(subscriber.email.clone()),
+        SubscriberName::parse(subscriber.name.clone()),
+    ) {
+        (Ok(email), Ok(name)) => NewSubscriber { email, name },
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping an email. The stored details of the subscriber are invalid",
+            );
+            return Ok(());
+        }
+    };
+
+    match queued.email {
+        OutboxEmail::Confirmation => {
+            // Stored first, for the link to never be sent without it. A
+            // failed send rolls it back along with the job.
+            let token = generate_subscription_token();
+            store_token(&token, &queued.subscriber_id, transaction)
+                .await
+                .context("Failed to store a confirmation token")?;
+            send_confirmation_email(
+                context.email_client(lane),
+                new_subscriber,
+                &context.base_url,
+                &token,
+            )
+            .await?;
+        }
+        OutboxEmail::AlreadySubscribed => {
+            // Greeted with the name they subscribed with, not the one just submitted.
+            send_already_subscribed_email(
+                context.email_client(lane),
+                &new_subscriber.email,
+                new_subscriber.name.as_ref(),
+                &context.base_url,
+                &subscriber.unsubscribe_token,
+            )
+            .await?;
+        }
+        OutboxEmail::Welcome => match &context.welcome_email {
+            Some(template) => {
+                let unsubscribe_url =
+                    unsubscribe_url(&context.base_url, &subscriber.unsubscribe_token);
+                let message = template.render(&MergeFields {
+                    name: new_subscriber.name.as_ref(),
+                    email: new_subscriber.email.as_ref(),
+                    unsubscribe_url: Some(&unsubscribe_url),
+                })?;
+                context
+                    .email_client(lane)
+                    .send_email(
+                        &new_subscriber.email,
+                        &message.subject,
+                        &message.html_body,
+                        &message.text_body,
+                    )
+                    .await?;
+            }
+            None => tracing::info!("Skipping a welcome email. None is configured anymore"),
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+//! Templating for outbound emails.
+//!
+//! Email bodies are written by humans at runtime, so they cannot be compiled
+//! with `askama` like our pages: they support a small set of `{{ variable }}`
+//! placeholders instead, filled in for every recipient at send time.
+//! The rendered body is then wrapped in a shared layout (header and footer).
+use askama::{Html, MarkupDisplay, Template};
+
+/// Placeholders that can be used in an email template.
+pub const KNOWN_VARIABLES: [&str; 3] = ["name", "email", "unsubscribe_url"];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("`{{{{ {0} }}}}` is not a known variable. Use one of: name, email, unsubscribe_url")]
+    UnknownVariable(String),
+    #[error("A `{{{{ }}}}` placeholder is missing its variable name")]
+    MissingVariableName,
+    #[error("A `{{{{` placeholder is never closed")]
+    UnclosedPlaceholder,
+    #[error("No value is available for `{{{{ {0} }}}}` in this email")]
+    MissingValue(&'static str),
+    #[error("Failed to render the email layout")]
+    Layout(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(&'static str),
+}
+
+/// A parsed email template, guaranteed to only reference known variables.
+#[derive(Debug)]
+pub struct EmailTemplate(Vec<Segment>);
+
+impl EmailTemplate {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or(TemplateError::UnclosedPlaceholder)?;
+            let name = after_open[..end].trim();
+            if name.is_empty() {
+                return Err(TemplateError::MissingVariableName);
+            }
+            let variable = KNOWN_VARIABLES
+                .into_iter()
+                .find(|v| *v == name)
+                .ok_or_else(|| TemplateError::UnknownVariable(name.to_owned()))?;
+            segments.push(Segment::Variable(variable));
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// Render an HTML body on its own, without the email layout.
+    pub fn render_html(&self, fields: &MergeFields) -> Result<String, TemplateError> {
+        self.render(fields, true)
+    }
+
+    /// Values are HTML-escaped when `escape` is set: the template itself is
+    /// trusted, the subscriber details are not.
+    fn render(&self, fields: &MergeFields, escape: bool) -> Result<String, TemplateError> {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(s) => rendered.push_str(s),
+                Segment::Variable(name) => {
+                    let value = fields.get(name)?;
+                    if escape {
+                        rendered.push_str(&MarkupDisplay::new_unsafe(value, Html).to_string());
+                    } else {
+                        rendered.push_str(value);
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+/// Per-recipient values for the template placeholders.
+pub struct MergeFields<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    /// Not available to subscribers who have not confirmed yet.
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+impl<'a> MergeFields<'a> {
+    fn get(&self, variable: &'static str) -> Result<&'a str, TemplateError> {
+        match variable {
+            "name" => Ok(self.name),
+            "email" => Ok(self.email),
+            "unsubscribe_url" => self
+                .unsubscribe_url
+                .ok_or(TemplateError::MissingValue(variable)),
+            _ => Err(TemplateError::UnknownVariable(variable.to_owned())),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/layout.html")]
+struct HtmlLayout<'a> {
+    content: &'a str,
+    unsubscribe_url: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "email/layout.txt")]
+struct TextLayout<'a> {
+    content: &'a str,
+    unsubscribe_url: Option<&'a str>,
+}
+
+/// An email ready to be handed over to the `EmailClient`.
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+/// Subject, HTML and plain-text variants of an email.
+#[derive(Debug)]
+pub struct EmailTemplates {
+    subject: EmailTemplate,
+    html_body: EmailTemplate,
+    text_body: EmailTemplate,
+}
+
+impl EmailTemplates {
+    pub fn parse(subject: &str, html_body: &str, text_body: &str) -> Result<Self, TemplateError> {
+        Ok(Self {
+            subject: EmailTemplate::parse(subject)?,
+            html_body: EmailTemplate::parse(html_body)?,
+            text_body: EmailTemplate::parse(text_body)?,
+        })
+    }
+
+    pub fn render(&self, fields: &MergeFields) -> Result<RenderedEmail, TemplateError> {
+        let html_content = self.html_body.render(fields, true)?;
+        let text_content = self.text_body.render(fields, false)?;
+        let html_body = HtmlLayout {
+            content: &html_content,
+            unsubscribe_url: fields.unsubscribe_url,
+        }
+        .render()
+        .map_err(|e| TemplateError::Layout(e.to_string()))?;
+        let text_body = TextLayout {
+            content: &text_content,
+            unsubscribe_url: fields.unsubscribe_url,
+        }
+        .render()
+        .map_err(|e| TemplateError::Layout(e.to_string()))?;
+
+        Ok(RenderedEmail {
+            subject: self.subject.render(fields, false)?,
+            html_body,
+            text_body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err_eq, assert_ok};
+
+    use super::{EmailTemplate, EmailTemplates, MergeFields, TemplateError};
+
+    fn fields() -> MergeFields<'static> {
+        MergeFields {
+            name: "Ursula <3",
+            email: "ursula@example.com",
+            unsubscribe_url: Some("https://example.com/unsubscribe?token=abc"),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_the_subscriber_details() {
+        let template = assert_ok!(EmailTemplate::parse("Hi {{ name }} ({{email}})!"));
+        let rendered = assert_ok!(template.render(&fields(), false));
+        assert_eq!(rendered, "Hi Ursula <3 (ursula@example.com)!");
+    }
+
+    #[test]
+    fn values_are_escaped_in_html_bodies() {
+        let template = assert_ok!(EmailTemplate::parse("<p>Hi {{ name }}</p>"));
+        let rendered = assert_ok!(template.render(&fields(), true));
+        assert_eq!(rendered, "<p>Hi Ursula &lt;3</p>");
+    }
+
+    #[test]
+    fn templates_without_placeholders_are_left_untouched() {
+        let template = assert_ok!(EmailTemplate::parse("Curly { braces } are fine"));
+        let rendered = assert_ok!(template.render(&fields(), false));
+        assert_eq!(rendered, "Curly { braces } are fine");
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err_eq!(
+            EmailTemplate::parse("Hi {{ first_name }}"),
+            TemplateError::UnknownVariable("first_name".into())
+        );
+    }
+
+    #[test]
+    fn empty_placeholders_are_rejected() {
+        assert_err_eq!(
+            EmailTemplate::parse("Hi {{ }}"),
+            TemplateError::MissingVariableName
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err_eq!(
+            EmailTemplate::parse("Hi {{ name"),
+            TemplateError::UnclosedPlaceholder
+        );
+    }
+
+    #[test]
+    fn rendering_fails_if_a_value_is_not_available() {
+        let template = assert_ok!(EmailTemplate::parse("Bye: {{ unsubscribe_url }}"));
+        let fields = MergeFields {
+            unsubscribe_url: None,
+            ..fields()
+        };
+        assert_err_eq!(
+            template.render(&fields, false),
+            TemplateError::MissingValue("unsubscribe_url")
+        );
+    }
+
+    #[test]
+    fn bodies_are_wrapped_in_the_layout() {
+        let templates = assert_ok!(EmailTemplates::parse(
+            "News for {{ name }}",
+            "<p>Hello!</p>",
+            "Hello!"
+        ));
+        let email = assert_ok!(templates.render(&fields()));
+        assert_eq!(email.subject, "News for Ursula <3");
+        assert!(email.html_body.contains("<p>Hello!</p>"));
+        assert!(email
+            .html_body
+            .contains(r#"href="https://example.com/unsubscribe?token=abc""#));
+        assert!(email.text_body.contains("Hello!"));
+        assert!(email
+            .text_body
+            .contains("https://example.com/unsubscribe?token=abc"));
+    }
+}
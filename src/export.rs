@@ -0,0 +1,449 @@
+//! Encoding of exported rows, as CSV or JSON Lines.
+//!
+//! Exports are streamed: rows are encoded one at a time into a buffer,
+//! which is handed over to the response body whenever it is large enough.
+use actix_web::{http::header::ContentDisposition, web::Bytes, HttpResponse};
+use futures_util::{Stream, TryStreamExt};
+use serde::ser::{Error as _, Impossible, SerializeStruct, Serializer};
+use serde::Serialize;
+
+/// Buffered bytes are flushed into the response past this size.
+pub const CHUNK_SIZE_BYTES: usize = 64 * 1024;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Serve `chunks` as a file named `{name}.{extension}`.
+///
+/// The status code is sent before the first row is read: errors past that
+/// point can only be logged, and abort the download.
+pub fn export_response<S>(format: ExportFormat, name: &str, chunks: S) -> HttpResponse
+where
+    S: Stream<Item = Result<Bytes, anyhow::Error>> + 'static,
+{
+    let chunks = chunks.inspect_err(
+        |e| tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to export rows"),
+    );
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "{}.{}",
+            name,
+            format.extension()
+        )))
+        .streaming(chunks)
+}
+
+pub struct RowEncoder {
+    format: ExportFormat,
+    buffer: Vec<u8>,
+    wrote_csv_headers: bool,
+}
+
+impl RowEncoder {
+    /// CSV exports start with a header row, named after the fields of the rows.
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            buffer: Vec::new(),
+            wrote_csv_headers: false,
+        }
+    }
+
+    pub fn push<T: Serialize>(&mut self, row: &T) -> Result<(), anyhow::Error> {
+        match self.format {
+            ExportFormat::Csv => {
+                // Collected first, for the cells to be escaped one by one.
+                let mut record = CsvRecord::default();
+                row.serialize(&mut record)?;
+                let mut writer = csv::Writer::from_writer(&mut self.buffer);
+                if !self.wrote_csv_headers {
+                    writer.write_record(&record.headers)?;
+                    self.wrote_csv_headers = true;
+                }
+                writer.write_record(record.cells.iter().map(escape_csv_formula))?;
+                writer.flush()?;
+            }
+            ExportFormat::Jsonl => {
+                serde_json::to_writer(&mut self.buffer, row)?;
+                self.buffer.push(b'\n');
+            }
+        }
+        Ok(())
+    }
+
+    /// Size of the encoded rows that have not been taken yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.buffer))
+    }
+}
+
+/// Spreadsheets run cells starting with these as formulas.
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Prefix `cell` with `'` if a spreadsheet would run it as a formula: the
+/// names and emails we export are written by anyone who subscribes.
+pub fn escape_csv_formula(cell: &str) -> String {
+    if cell.starts_with(FORMULA_PREFIXES) {
+        format!("'{}", cell)
+    } else {
+        cell.to_owned()
+    }
+}
+
+/// The field names and cells of a row, as serialized.
+///
+/// Rows are flat structs: nested values cannot be exported as CSV.
+#[derive(Default)]
+struct CsvRecord {
+    headers: csv::StringRecord,
+    cells: csv::StringRecord,
+}
+
+/// Serializer methods that are not supported, for types that cannot be
+/// written as a row or as a cell.
+macro_rules! unsupported {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, csv::Error> {
+                Err(csv::Error::custom(concat!(
+                    "Cannot export the output of `",
+                    stringify!($method),
+                    "` as CSV"
+                )))
+            }
+        )*
+    };
+}
+
+impl Serializer for &mut CsvRecord {
+    type Ok = ();
+    type Error = csv::Error;
+    type SerializeSeq = Impossible<(), csv::Error>;
+    type SerializeTuple = Impossible<(), csv::Error>;
+    type SerializeTupleStruct = Impossible<(), csv::Error>;
+    type SerializeTupleVariant = Impossible<(), csv::Error>;
+    type SerializeMap = Impossible<(), csv::Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), csv::Error>;
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, csv::Error> {
+        Ok(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), csv::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), csv::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), csv::Error> {
+        Err(csv::Error::custom("Cannot export an enum as a CSV row"))
+    }
+
+    unsupported! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+impl SerializeStruct for &mut CsvRecord {
+    type Ok = ();
+    type Error = csv::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), csv::Error> {
+        self.headers.push_field(key);
+        self.cells.push_field(&value.serialize(CsvCell)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), csv::Error> {
+        Ok(())
+    }
+}
+
+/// A value of a row, written as text. Missing values are empty cells.
+struct CsvCell;
+
+impl Serializer for CsvCell {
+    type Ok = String;
+    type Error = csv::Error;
+    type SerializeSeq = Impossible<String, csv::Error>;
+    type SerializeTuple = Impossible<String, csv::Error>;
+    type SerializeTupleStruct = Impossible<String, csv::Error>;
+    type SerializeTupleVariant = Impossible<String, csv::Error>;
+    type SerializeMap = Impossible<String, csv::Error>;
+    type SerializeStruct = Impossible<String, csv::Error>;
+    type SerializeStructVariant = Impossible<String, csv::Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, csv::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, csv::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, csv::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, csv::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, csv::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, csv::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, csv::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, csv::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, csv::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, csv::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, csv::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, csv::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, csv::Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_none(self) -> Result<String, csv::Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, csv::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, csv::Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String, csv::Error> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<String, csv::Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, csv::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String, csv::Error> {
+        Err(csv::Error::custom(
+            "Cannot export an enum with a value as a CSV cell",
+        ))
+    }
+
+    unsupported! {
+        serialize_bytes(&[u8]) -> String;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+
+    use super::{escape_csv_formula, ExportFormat, RowEncoder};
+
+    #[derive(serde::Serialize)]
+    struct Row {
+        email: &'static str,
+        name: &'static str,
+    }
+
+    fn rows() -> [Row; 2] {
+        [
+            Row {
+                email: "ursula@example.com",
+                name: "Ursula",
+            },
+            Row {
+                email: "le.guin@example.com",
+                name: "Le \"Guin\", Ursula",
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_exports_have_a_single_header_row() {
+        let mut encoder = RowEncoder::new(ExportFormat::Csv);
+        let [first, second] = rows();
+        assert_ok!(encoder.push(&first));
+        let first_chunk = encoder.take();
+        assert_ok!(encoder.push(&second));
+        let second_chunk = encoder.take();
+        assert_eq!(first_chunk, "email,name\nursula@example.com,Ursula\n");
+        assert_eq!(
+            second_chunk,
+            "le.guin@example.com,\"Le \"\"Guin\"\", Ursula\"\n"
+        );
+        assert_eq!(encoder.buffered(), 0);
+    }
+
+    #[test]
+    fn csv_cells_that_look_like_formulas_are_escaped() {
+        let mut encoder = RowEncoder::new(ExportFormat::Csv);
+        assert_ok!(encoder.push(&Row {
+            email: "ursula@example.com",
+            name: "=HYPERLINK(\"https://example.com\")",
+        }));
+        assert_eq!(
+            encoder.take(),
+            "email,name\nursula@example.com,\"'=HYPERLINK(\"\"https://example.com\"\")\"\n"
+        );
+    }
+
+    #[test]
+    fn missing_csv_cells_are_empty() {
+        #[derive(serde::Serialize)]
+        struct RowWithOption {
+            email: &'static str,
+            ip_address: Option<&'static str>,
+        }
+
+        let mut encoder = RowEncoder::new(ExportFormat::Csv);
+        assert_ok!(encoder.push(&RowWithOption {
+            email: "ursula@example.com",
+            ip_address: None,
+        }));
+        assert_ok!(encoder.push(&RowWithOption {
+            email: "le.guin@example.com",
+            ip_address: Some("127.0.0.1"),
+        }));
+        assert_eq!(
+            encoder.take(),
+            "email,ip_address\nursula@example.com,\nle.guin@example.com,127.0.0.1\n"
+        );
+    }
+
+    #[test]
+    fn only_cells_starting_like_a_formula_are_escaped() {
+        for cell in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(escape_csv_formula(cell), format!("'{}", cell));
+        }
+        for cell in ["Ursula", "1-1", "a=b", "ursula@example.com", ""] {
+            assert_eq!(escape_csv_formula(cell), cell);
+        }
+    }
+
+    #[test]
+    fn json_lines_exports_have_one_object_per_line() {
+        let mut encoder = RowEncoder::new(ExportFormat::Jsonl);
+        for row in rows() {
+            assert_ok!(encoder.push(&row));
+        }
+        assert_eq!(
+            encoder.take(),
+            "{\"email\":\"ursula@example.com\",\"name\":\"Ursula\"}\n\
+            {\"email\":\"le.guin@example.com\",\"name\":\"Le \\\"Guin\\\", Ursula\"}\n"
+        );
+    }
+}
@@ -19,7 +19,8 @@ impl PgHasArrayType for HeaderPairRecord {
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    // Boxed, for the enum not to be as large as a transaction.
+    StartProcessing(Box<Transaction<'static, Postgres>>),
     ReturnSavedResponse(HttpResponse),
 }
 
@@ -47,7 +48,7 @@ ON CONFLICT DO NOTHING
     .rows_affected();
 
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
+        Ok(NextAction::StartProcessing(Box::new(transaction)))
     } else {
         let saved_response = get_saved_response(pool, idempotency_key, user_id)
             .await?
@@ -1,10 +1,13 @@
 use sqlx::{PgPool, Postgres, Transaction};
-use std::time::Duration;
-use tracing::{field::display, Span};
 use uuid::Uuid;
 
-use crate::{configuration::Settings, startup::get_connection_pool};
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::{SubscriberEmail, SubscriptionStatus},
+    email_client::EmailClient,
+    email_template::{EmailTemplates, MergeFields},
+    jobs::{Job, JobContext, Lane},
+    routes::unsubscribe_url,
+};
 
 struct NewsletterIssue {
     title: String,
@@ -12,16 +15,29 @@ struct NewsletterIssue {
     html_content: String,
 }
 
-pub enum ExecutionOutcome {
-    TaskCompleted,
-    EmptyQueue,
+/// Recorded in `issue_delivery_log` for every task.
+#[derive(Debug, Clone, Copy)]
+enum DeliveryOutcome {
+    Sent,
+    /// Rendering the issue or calling the email API failed.
+    Failed,
+    /// The subscriber is gone or their details are invalid.
+    Skipped,
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
 
-    worker_loop(connection_pool, email_client).await
+struct Subscriber {
+    name: String,
+    unsubscribe_token: String,
 }
 
 #[tracing::instrument(skip_all)]
@@ -41,124 +57,243 @@ newsletter_issue_id = $1
     Ok(issue)
 }
 
-#[tracing::instrument(
-skip_all,
-fields(
-newsletter_issue_id = tracing::field::Empty,
-subscriber_email = tracing::field::Empty
-),
-err
-)]
-pub async fn try_execute_task(
-    pool: &PgPool,
-    email_client: &EmailClient,
-) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
+#[tracing::instrument(skip_all)]
+async fn get_subscriber(pool: &PgPool, email: &str) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+SELECT name, unsubscribe_token
+FROM subscriptions
+WHERE
+email = $1
+"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(subscriber)
+}
+
+/// Deliver an issue to one of the confirmed subscribers.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DeliverIssue {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_email: String,
+}
+
+impl Job for DeliverIssue {
+    const KIND: &'static str = "deliver_issue";
+    const LANE: Lane = Lane::Bulk;
+    // Failed deliveries are logged rather than retried, see `deliver_issue`:
+    // retries only cover failures to record the outcome.
+    const MAX_ATTEMPTS: i16 = 3;
+
+    fn unique_key(&self) -> Option<String> {
+        Some(delivery_key(
+            self.newsletter_issue_id,
+            &self.subscriber_email,
+        ))
     }
 
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
+    #[tracing::instrument(
+    skip_all,
+    fields(
+    newsletter_issue_id = %self.newsletter_issue_id,
+    subscriber_email = %self.subscriber_email
+    )
+    )]
+    async fn run(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        let pool = &context.pool;
+        let outcome = match SubscriberEmail::parse(self.subscriber_email.clone()) {
+            Ok(email) => {
+                let issue = get_issue(pool, self.newsletter_issue_id).await?;
+                match get_subscriber(pool, email.as_ref()).await? {
+                    Some(subscriber) => {
+                        deliver_issue(
+                            context.email_client(Self::LANE),
+                            &context.base_url,
+                            &issue,
+                            &email,
+                            &subscriber,
+                        )
+                        .await
+                    }
+                    None => {
+                        tracing::error!(
+                            "Skipping a subscriber that no longer exists. \
+                            They were deleted after the issue was published.",
+                        );
+                        DeliveryOutcome::Skipped
+                    }
+                }
+            }
+            Err(e) => {
                 tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
                 );
+                DeliveryOutcome::Skipped
             }
-        }
+        };
+        log_delivery(transaction, &self, outcome).await
+    }
+}
+
+fn delivery_key(newsletter_issue_id: Uuid, subscriber_email: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        DeliverIssue::KIND,
+        newsletter_issue_id,
+        subscriber_email
+    )
+}
+
+/// Queue the delivery of an issue to every confirmed subscriber.
+/// Returns how many deliveries were queued.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    // Same payload and unique key as `DeliverIssue`.
+    let n_jobs = sqlx::query!(
+        r#"
+INSERT INTO background_jobs (
+    job_id, kind, lane, payload, priority, run_at, max_attempts, unique_key, created_at
+)
+SELECT
+    gen_random_uuid(),
+    $2,
+    $6,
+    jsonb_build_object('newsletter_issue_id', $1::uuid, 'subscriber_email', email),
+    $3,
+    now(),
+    $4,
+    $2 || ':' || $1::text || ':' || email,
+    now()
+FROM subscriptions
+WHERE status = $5
+ON CONFLICT (unique_key) DO NOTHING
+"#,
+        newsletter_issue_id,
+        DeliverIssue::KIND,
+        DeliverIssue::PRIORITY,
+        DeliverIssue::MAX_ATTEMPTS,
+        SubscriptionStatus::Confirmed.as_str(),
+        DeliverIssue::LANE.as_str(),
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(n_jobs)
+}
+
+/// Personalise the issue for `subscriber` and send it.
+///
+/// Failures are logged rather than returned: retrying would not help with
+/// an invalid template, and would spam the subscriber if the API call went through.
+async fn deliver_issue(
+    email_client: &EmailClient,
+    base_url: &str,
+    issue: &NewsletterIssue,
+    email: &SubscriberEmail,
+    subscriber: &Subscriber,
+) -> DeliveryOutcome {
+    let unsubscribe_url = unsubscribe_url(base_url, &subscriber.unsubscribe_token);
+    let fields = MergeFields {
+        name: &subscriber.name,
+        email: email.as_ref(),
+        unsubscribe_url: Some(&unsubscribe_url),
+    };
+    let rendered = EmailTemplates::parse(&issue.title, &issue.html_content, &issue.text_content)
+        .and_then(|templates| templates.render(&fields));
+    let rendered = match rendered {
+        Ok(rendered) => rendered,
         Err(e) => {
             tracing::error!(
-            error.cause_chain = ?e,
             error.message = %e,
-            "Skipping a confirmed subscriber. \
-            Their stored contact details are invalid",
+            "Failed to render issue for a confirmed subscriber. \
+            Skipping.",
             );
+            return DeliveryOutcome::Failed;
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
 
-    Ok(ExecutionOutcome::TaskCompleted)
+    if let Err(e) = email_client
+        .send_email(
+            email,
+            &rendered.subject,
+            &rendered.html_body,
+            &rendered.text_body,
+        )
+        .await
+    {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to deliver issue to a confirmed subscriber. \
+        Skipping.",
+        );
+        return DeliveryOutcome::Failed;
+    }
+    DeliveryOutcome::Sent
 }
 
-type PgTransaction = Transaction<'static, Postgres>;
-
+/// Log the outcome of a delivery, and mark the issue as delivered after the
+/// last one.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
-    pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+async fn log_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    delivery: &DeliverIssue,
+    outcome: DeliveryOutcome,
+) -> Result<(), anyhow::Error> {
+    // Workers delivering the last emails of an issue take turns:
+    // otherwise each of them could still see the job of the other one
+    // and the issue would never be marked as delivered.
+    sqlx::query!(
         r#"
-SELECT newsletter_issue_id, subscriber_email
-FROM issue_delivery_queue
+SELECT newsletter_issue_id
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
 FOR UPDATE
-SKIP LOCKED
-LIMIT 1
 "#,
+        delivery.newsletter_issue_id,
     )
-    .fetch_optional(&mut transaction)
+    .fetch_one(&mut *transaction)
     .await?;
-
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
-}
-
-#[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
-) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
-DELETE FROM issue_delivery_queue
+INSERT INTO issue_delivery_log (newsletter_issue_id, subscriber_email, outcome, attempted_at)
+VALUES ($1, $2, $3, now())
+"#,
+        delivery.newsletter_issue_id,
+        delivery.subscriber_email,
+        outcome.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET status = 'delivered', updated_at = now()
 WHERE
 newsletter_issue_id = $1 AND
-subscriber_email = $2
+NOT EXISTS (
+    SELECT 1 FROM background_jobs
+    WHERE kind = $2 AND payload ->> 'newsletter_issue_id' = $3
+)
 "#,
-        issue_id,
-        email
+        delivery.newsletter_issue_id,
+        DeliverIssue::KIND,
+        delivery.newsletter_issue_id.to_string(),
     )
-    .execute(&mut transaction)
+    .execute(&mut *transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
-
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
-            }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-        }
-    }
-}
@@ -0,0 +1,179 @@
+//! Listing and full-text search of newsletter issues.
+//!
+//! Backs both the public archive and the admin issue list.
+use askama::{Html, MarkupDisplay};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const PAGE_SIZE: i64 = 20;
+/// Deeper pages are clamped to it, which keeps offsets well within range.
+const MAX_PAGE: i64 = 10_000;
+
+// `ts_headline` wraps matches in these tags, before the snippet is escaped.
+const HIGHLIGHT_START: &str = "<mark>";
+const HIGHLIGHT_STOP: &str = "</mark>";
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SearchParameters {
+    /// Search terms, in the syntax of web search engines (`"quoted phrase"`, `-excluded`).
+    /// Issues are listed from the most recent when empty.
+    #[serde(default)]
+    pub q: String,
+    pub page: Option<i64>,
+}
+
+impl SearchParameters {
+    /// 1-based, out of range values are clamped.
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Visibility {
+    PublicOnly,
+    All,
+}
+
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub is_public: bool,
+    /// `delivering` until every subscriber has been sent the issue, then `delivered`.
+    pub status: String,
+    pub published_at: DateTime<Utc>,
+    /// Unknown for issues published before authors were recorded.
+    pub author: Option<String>,
+    /// Excerpt of the issue with the search terms highlighted, safe to embed as HTML.
+    pub snippet: Option<String>,
+}
+
+pub struct IssuePage {
+    pub issues: Vec<IssueSummary>,
+    pub page: i64,
+    pub has_next_page: bool,
+}
+
+#[tracing::instrument(name = "Search newsletter issues", skip(pool))]
+pub async fn search_issues(
+    pool: &PgPool,
+    parameters: &SearchParameters,
+    visibility: Visibility,
+) -> Result<IssuePage, sqlx::Error> {
+    let query = parameters.q.trim();
+    let page = parameters.page();
+    let public_only = matches!(visibility, Visibility::PublicOnly);
+    let headline_options = format!(
+        "StartSel={}, StopSel={}, MaxFragments=2, MinWords=5, MaxWords=20",
+        HIGHLIGHT_START, HIGHLIGHT_STOP
+    );
+    // One more row than needed tells us whether there is a next page.
+    let mut rows = sqlx::query!(
+        r#"
+SELECT
+    newsletter_issue_id,
+    title,
+    slug,
+    is_public,
+    status,
+    published_at,
+    username AS "author?",
+    CASE WHEN $1 = '' THEN NULL
+    ELSE ts_headline('english', text_content, websearch_to_tsquery('english', $1), $3)
+    END AS snippet
+FROM newsletter_issues
+LEFT JOIN users ON users.user_id = newsletter_issues.author_user_id
+WHERE (is_public OR NOT $2)
+AND ($1 = '' OR search_vector @@ websearch_to_tsquery('english', $1))
+ORDER BY
+    CASE WHEN $1 = '' THEN 0
+    ELSE ts_rank(search_vector, websearch_to_tsquery('english', $1))
+    END DESC,
+    published_at DESC
+LIMIT $4 OFFSET $5
+"#,
+        query,
+        public_only,
+        headline_options,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let has_next_page = rows.len() as i64 > PAGE_SIZE;
+    rows.truncate(PAGE_SIZE as usize);
+    let issues = rows
+        .into_iter()
+        .map(|r| IssueSummary {
+            newsletter_issue_id: r.newsletter_issue_id,
+            title: r.title,
+            slug: r.slug,
+            is_public: r.is_public,
+            status: r.status,
+            published_at: r.published_at,
+            author: r.author,
+            snippet: r.snippet.as_deref().map(escape_snippet),
+        })
+        .collect();
+    Ok(IssuePage {
+        issues,
+        page,
+        has_next_page,
+    })
+}
+
+/// Escape the excerpt of the issue, except for the highlighting tags.
+fn escape_snippet(snippet: &str) -> String {
+    snippet
+        .split(HIGHLIGHT_START)
+        .map(|chunk| {
+            chunk
+                .split(HIGHLIGHT_STOP)
+                .map(|s| MarkupDisplay::new_unsafe(s, Html).to_string())
+                .collect::<Vec<_>>()
+                .join(HIGHLIGHT_STOP)
+        })
+        .collect::<Vec<_>>()
+        .join(HIGHLIGHT_START)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{prelude::any, proptest};
+
+    use super::{escape_snippet, SearchParameters, MAX_PAGE, PAGE_SIZE};
+
+    fn parameters(page: Option<i64>) -> SearchParameters {
+        SearchParameters {
+            q: String::new(),
+            page,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pages_are_clamped_to_a_valid_range(page in any::<i64>()) {
+            let page = parameters(Some(page)).page();
+            assert!((1..=MAX_PAGE).contains(&page));
+            // Neither the offset nor the link to the next page overflow.
+            assert!((page - 1).checked_mul(PAGE_SIZE).is_some());
+            assert!(page.checked_add(1).is_some());
+        }
+    }
+
+    #[test]
+    fn the_first_page_is_the_default() {
+        assert_eq!(parameters(None).page(), 1);
+    }
+
+    #[test]
+    fn snippets_are_escaped_but_keep_their_highlights() {
+        assert_eq!(
+            escape_snippet("a <script> about <mark>Rust</mark> & more"),
+            "a &lt;script&gt; about <mark>Rust</mark> &amp; more"
+        );
+    }
+}
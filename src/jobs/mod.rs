@@ -0,0 +1,13 @@
+//! A queue of typed jobs, stored in Postgres and shared by all the
+//! asynchronous work of the application.
+//!
+//! Jobs are queued in the transaction of the change that calls for them, and
+//! run by `run_worker_until_stopped` in order of priority, once due. Failed
+//! jobs are retried with a backoff, and recurring jobs queue their next run.
+mod queue;
+mod schedule;
+mod worker;
+
+pub use queue::*;
+pub use schedule::*;
+pub use worker::*;
@@ -0,0 +1,173 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::future::Future;
+use uuid::Uuid;
+
+use super::{JobContext, Schedule};
+
+/// Jobs are run by workers of two lanes, with a concurrency of their own.
+///
+/// Transactional workers only run transactional jobs, and bulk workers run
+/// waiting transactional jobs first: emails people are waiting for are never
+/// stuck behind a newsletter issue. Each lane sends through its own message
+/// stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Transactional,
+    Bulk,
+}
+
+impl Lane {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Transactional => "transactional",
+            Self::Bulk => "bulk",
+        }
+    }
+}
+
+impl std::fmt::Display for Lane {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Work for the background worker, stored as JSON in `background_jobs.payload`.
+pub trait Job: Serialize + DeserializeOwned + Send {
+    /// Stored in `background_jobs.kind`, to know how to run the job.
+    const KIND: &'static str;
+    const LANE: Lane;
+    /// Jobs with a higher priority run first, within their lane.
+    const PRIORITY: i16 = 0;
+    /// Failed jobs are retried, with a backoff, until they have been
+    /// attempted this many times.
+    const MAX_ATTEMPTS: i16 = 1;
+
+    /// Jobs sharing a key are only queued once at a time.
+    fn unique_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Changes made through `transaction` are committed along with the
+    /// removal of the job, or rolled back if it fails.
+    fn run(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
+
+/// A job as stored in `background_jobs`.
+pub(super) struct JobRow {
+    pub kind: String,
+    pub lane: String,
+    pub payload: serde_json::Value,
+    pub priority: i16,
+    pub max_attempts: i16,
+    pub unique_key: Option<String>,
+    pub schedule: Option<String>,
+}
+
+/// Queue `job`, to run once `transaction` is committed.
+/// Returns `false` if a job with the same unique key is already queued.
+pub async fn enqueue<J: Job>(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &J,
+) -> Result<bool, anyhow::Error> {
+    enqueue_at(transaction, job, Utc::now()).await
+}
+
+/// Same as `enqueue`, for a job that must not run before `run_at`.
+#[tracing::instrument(name = "Queue a job", skip(transaction, job), fields(kind = J::KIND))]
+pub async fn enqueue_at<J: Job>(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &J,
+    run_at: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    let row = JobRow {
+        kind: J::KIND.into(),
+        lane: J::LANE.as_str().into(),
+        payload: serde_json::to_value(job).context("Failed to serialize a job")?,
+        priority: J::PRIORITY,
+        max_attempts: J::MAX_ATTEMPTS,
+        unique_key: job.unique_key(),
+        schedule: None,
+    };
+    insert_job(transaction, &row, run_at).await
+}
+
+pub(super) async fn insert_job(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &JobRow,
+    run_at: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+INSERT INTO background_jobs (
+    job_id, kind, lane, payload, priority, run_at, max_attempts, unique_key, schedule, created_at
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+ON CONFLICT (unique_key) DO NOTHING
+"#,
+        Uuid::new_v4(),
+        job.kind,
+        job.lane,
+        job.payload,
+        job.priority,
+        run_at,
+        job.max_attempts,
+        job.unique_key,
+        job.schedule,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to queue a job")?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+/// Queue the next run of `job`, repeating on `schedule` from then on.
+///
+/// Recurring jobs are keyed on their kind: this is a no-op if the job is
+/// already queued with the same schedule, whatever the number of workers.
+#[tracing::instrument(name = "Schedule a recurring job", skip(pool, job), fields(kind = J::KIND, %schedule))]
+pub async fn schedule_recurring<J: Job>(
+    pool: &PgPool,
+    job: &J,
+    schedule: &Schedule,
+) -> Result<(), anyhow::Error> {
+    let run_at = schedule
+        .next_after(Utc::now())
+        .context("The schedule has no run left")?;
+    // A job queued with another schedule is moved to the new one.
+    sqlx::query!(
+        r#"
+INSERT INTO background_jobs (
+    job_id, kind, lane, payload, priority, run_at, max_attempts, unique_key, schedule, created_at
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+ON CONFLICT (unique_key) DO UPDATE
+SET schedule = EXCLUDED.schedule, run_at = EXCLUDED.run_at
+WHERE background_jobs.schedule IS DISTINCT FROM EXCLUDED.schedule
+"#,
+        Uuid::new_v4(),
+        J::KIND,
+        J::LANE.as_str(),
+        serde_json::to_value(job).context("Failed to serialize a job")?,
+        J::PRIORITY,
+        run_at,
+        J::MAX_ATTEMPTS,
+        recurring_job_key(J::KIND),
+        schedule.to_string(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to schedule a recurring job")?;
+    Ok(())
+}
+
+fn recurring_job_key(kind: &str) -> String {
+    format!("recurring:{}", kind)
+}
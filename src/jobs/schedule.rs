@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+/// When a recurring job runs: either `@every <n>` with a unit among `s`, `m`
+/// and `h`, or a cron expression with seconds, in UTC. For instance,
+/// `0 30 3 * * *` is every day at 03:30.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Schedule {
+    Every(std::time::Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn parse(schedule: &str) -> Result<Self, String> {
+        match schedule.strip_prefix("@every ") {
+            Some(interval) => parse_interval(interval.trim()).map(Self::Every),
+            None => cron::Schedule::from_str(schedule)
+                .map(|s| Self::Cron(Box::new(s)))
+                .map_err(|e| format!("`{}` is not a valid cron expression: {}", schedule, e)),
+        }
+    }
+
+    /// `None` if the schedule has no run left after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Every(interval) => Some(after + chrono::Duration::from_std(*interval).ok()?),
+            Self::Cron(schedule) => schedule.after(&after).next(),
+        }
+    }
+}
+
+fn parse_interval(interval: &str) -> Result<std::time::Duration, String> {
+    let invalid = || format!("`{}` is not a valid interval", interval);
+    let split = interval.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = interval.split_at(split);
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(invalid()),
+    };
+    match amount.parse::<u64>() {
+        Ok(amount) if amount > 0 => amount
+            .checked_mul(seconds_per_unit)
+            .map(std::time::Duration::from_secs)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(schedule: String) -> Result<Self, Self::Error> {
+        Self::parse(&schedule)
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Every(interval) => write!(f, "@every {}s", interval.as_secs()),
+            Self::Cron(schedule) => write!(f, "{}", schedule),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok, assert_ok_eq, assert_some_eq};
+    use proptest::proptest;
+    use std::time::Duration;
+
+    use super::Schedule;
+
+    #[test]
+    fn intervals_are_parsed_in_seconds_minutes_or_hours() {
+        assert_ok_eq!(
+            Schedule::parse("@every 45s"),
+            Schedule::Every(Duration::from_secs(45))
+        );
+        assert_ok_eq!(
+            Schedule::parse("@every 15m"),
+            Schedule::Every(Duration::from_secs(15 * 60))
+        );
+        assert_ok_eq!(
+            Schedule::parse("@every 2h"),
+            Schedule::Every(Duration::from_secs(2 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for schedule in [
+            "",
+            "@every",
+            "@every 0s",
+            "@every -5m",
+            "@every 5",
+            "@every 5d",
+            "@every m",
+            "every day",
+            "* * *",
+        ] {
+            assert_err!(Schedule::parse(schedule), "{}", schedule);
+        }
+    }
+
+    #[test]
+    fn intervals_run_that_long_after_the_previous_run() {
+        let schedule = Schedule::parse("@every 90m").unwrap();
+        let previous = Utc.with_ymd_and_hms(2023, 8, 16, 23, 0, 0).unwrap();
+        assert_some_eq!(
+            schedule.next_after(previous),
+            Utc.with_ymd_and_hms(2023, 8, 17, 0, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn cron_expressions_run_at_their_next_match() {
+        let schedule = Schedule::parse("0 30 3 * * *").unwrap();
+        let before = Utc.with_ymd_and_hms(2023, 8, 16, 1, 0, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2023, 8, 16, 4, 0, 0).unwrap();
+        assert_some_eq!(
+            schedule.next_after(before),
+            Utc.with_ymd_and_hms(2023, 8, 16, 3, 30, 0).unwrap()
+        );
+        assert_some_eq!(
+            schedule.next_after(after),
+            Utc.with_ymd_and_hms(2023, 8, 17, 3, 30, 0).unwrap()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn schedules_are_parsed_back_from_their_stored_value(seconds in 1..1_000_000u64) {
+            let schedule = Schedule::Every(Duration::from_secs(seconds));
+            assert_ok_eq!(Schedule::parse(&schedule.to_string()), schedule);
+        }
+    }
+
+    #[test]
+    fn cron_expressions_are_parsed_back_from_their_stored_value() {
+        let schedule = assert_ok!(Schedule::parse("0 0 */6 * * Mon-Fri"));
+        assert_ok_eq!(Schedule::parse(&schedule.to_string()), schedule);
+    }
+}
@@ -0,0 +1,329 @@
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use super::{insert_job, schedule_recurring, Job, JobRow, Lane, Schedule};
+use crate::{
+    configuration::{PendingSubscriptionsSettings, Settings},
+    data_subject::DataRequestLink,
+    email_client::EmailClient,
+    email_outbox::{ImportConfirmationEmail, QueuedEmail},
+    email_template::EmailTemplates,
+    issue_delivery_worker::DeliverIssue,
+    pending_subscriptions::{PurgeStalePendingSubscriptions, SendPendingReminders},
+    startup::get_connection_pool,
+};
+
+/// Doubled after every failed attempt.
+const FIRST_RETRY_DELAY_SECONDS: i64 = 30;
+
+#[derive(Debug)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// What jobs need to run.
+pub struct JobContext {
+    pub pool: PgPool,
+    pub transactional_email_client: EmailClient,
+    pub bulk_email_client: EmailClient,
+    pub base_url: String,
+    pub pending_subscriptions: PendingSubscriptionsSettings,
+    /// Sent once a subscription is confirmed, if configured.
+    pub welcome_email: Option<EmailTemplates>,
+}
+
+impl JobContext {
+    /// Sends through the message stream of `lane`.
+    pub fn email_client(&self, lane: Lane) -> &EmailClient {
+        match lane {
+            Lane::Transactional => &self.transactional_email_client,
+            Lane::Bulk => &self.bulk_email_client,
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let context = JobContext {
+        pool: get_connection_pool(&configuration.database),
+        transactional_email_client: configuration
+            .email_client
+            .clone()
+            .client(Lane::Transactional),
+        bulk_email_client: configuration.email_client.client(Lane::Bulk),
+        base_url: configuration.application.base_url,
+        pending_subscriptions: configuration.pending_subscriptions,
+        welcome_email: configuration
+            .welcome_email
+            .map(|e| e.templates())
+            .transpose()
+            .context("Invalid welcome email")?,
+    };
+    schedule_recurring_jobs(&context.pool, &context.pending_subscriptions).await?;
+
+    let settings = configuration.jobs;
+    let workers = std::iter::repeat(Lane::Transactional)
+        .take(settings.transactional_concurrency)
+        .chain(std::iter::repeat(Lane::Bulk).take(settings.bulk_concurrency))
+        .map(|lane| worker_loop(&context, lane));
+    futures_util::future::join_all(workers).await;
+    Ok(())
+}
+
+async fn worker_loop(context: &JobContext, lane: Lane) {
+    loop {
+        match try_execute_job(context, lane).await {
+            // People are waiting for some of these jobs: the queue is polled often.
+            Ok(ExecutionOutcome::EmptyQueue) | Err(_) => {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Every recurring job of the application.
+async fn schedule_recurring_jobs(
+    pool: &PgPool,
+    settings: &PendingSubscriptionsSettings,
+) -> Result<(), anyhow::Error> {
+    schedule_recurring(
+        pool,
+        &SendPendingReminders,
+        &Schedule::Every(settings.check_interval()),
+    )
+    .await?;
+    schedule_recurring(
+        pool,
+        &PurgeStalePendingSubscriptions,
+        &settings.purge_schedule,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Run a job with `payload`, according to its `kind`.
+async fn execute(
+    kind: &str,
+    payload: serde_json::Value,
+    transaction: &mut Transaction<'_, Postgres>,
+    context: &JobContext,
+) -> Result<(), anyhow::Error> {
+    match kind {
+        DeliverIssue::KIND => run::<DeliverIssue>(payload, transaction, context).await,
+        QueuedEmail::KIND => run::<QueuedEmail>(payload, transaction, context).await,
+        DataRequestLink::KIND => run::<DataRequestLink>(payload, transaction, context).await,
+        ImportConfirmationEmail::KIND => {
+            run::<ImportConfirmationEmail>(payload, transaction, context).await
+        }
+        SendPendingReminders::KIND => {
+            run::<SendPendingReminders>(payload, transaction, context).await
+        }
+        PurgeStalePendingSubscriptions::KIND => {
+            run::<PurgeStalePendingSubscriptions>(payload, transaction, context).await
+        }
+        _ => anyhow::bail!("`{}` is not a known kind of job", kind),
+    }
+}
+
+async fn run<J: Job>(
+    payload: serde_json::Value,
+    transaction: &mut Transaction<'_, Postgres>,
+    context: &JobContext,
+) -> Result<(), anyhow::Error> {
+    let job: J = serde_json::from_value(payload).context("Failed to parse the job")?;
+    job.run(transaction, context).await
+}
+
+struct QueuedJob {
+    job_id: Uuid,
+    n_attempts: i16,
+    row: JobRow,
+}
+
+/// Run the next job due for a worker of `lane`, if any.
+#[tracing::instrument(
+    skip(context),
+    fields(
+        job_id = tracing::field::Empty,
+        kind = tracing::field::Empty,
+        attempt = tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_job(
+    context: &JobContext,
+    lane: Lane,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = context.pool.begin().await?;
+    let job = match dequeue_job(&mut transaction, lane).await? {
+        Some(job) => job,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current()
+        .record("job_id", display(job.job_id))
+        .record("kind", display(&job.row.kind))
+        .record("attempt", job.n_attempts + 1);
+
+    // The changes of a failed job are rolled back with the savepoint, while
+    // its attempt is still recorded.
+    let mut savepoint = transaction.begin().await?;
+    delete_job(&mut savepoint, job.job_id).await?;
+    match execute(
+        &job.row.kind,
+        job.row.payload.clone(),
+        &mut savepoint,
+        context,
+    )
+    .await
+    {
+        Ok(()) => {
+            savepoint.commit().await?;
+            queue_next_run(&mut transaction, &job.row).await?;
+        }
+        Err(e) => {
+            savepoint.rollback().await?;
+            if job.n_attempts + 1 >= job.row.max_attempts {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to run a job. Giving up",
+                );
+                delete_job(&mut transaction, job.job_id).await?;
+                queue_next_run(&mut transaction, &job.row).await?;
+            } else {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to run a job. Retrying later",
+                );
+                retry_later(&mut transaction, &job, &e).await?;
+            }
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit a job")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_job(
+    transaction: &mut Transaction<'_, Postgres>,
+    lane: Lane,
+) -> Result<Option<QueuedJob>, anyhow::Error> {
+    // Bulk workers help with transactional jobs, never the other way around.
+    let lanes: &[Lane] = match lane {
+        Lane::Transactional => &[Lane::Transactional],
+        Lane::Bulk => &[Lane::Transactional, Lane::Bulk],
+    };
+    for lane in lanes {
+        if let Some(job) = dequeue_lane_job(&mut *transaction, *lane).await? {
+            return Ok(Some(job));
+        }
+    }
+    Ok(None)
+}
+
+/// One lane at a time, for the query to walk `background_jobs_queue_idx`.
+async fn dequeue_lane_job(
+    transaction: &mut Transaction<'_, Postgres>,
+    lane: Lane,
+) -> Result<Option<QueuedJob>, anyhow::Error> {
+    let job = sqlx::query!(
+        r#"
+SELECT job_id, kind, lane, payload, priority, n_attempts, max_attempts, unique_key, schedule
+FROM background_jobs
+WHERE lane = $1 AND run_at <= now()
+ORDER BY priority DESC, run_at
+LIMIT 1
+FOR UPDATE
+SKIP LOCKED
+"#,
+        lane.as_str(),
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to dequeue a job")?
+    .map(|r| QueuedJob {
+        job_id: r.job_id,
+        n_attempts: r.n_attempts,
+        row: JobRow {
+            kind: r.kind,
+            lane: r.lane,
+            payload: r.payload,
+            priority: r.priority,
+            max_attempts: r.max_attempts,
+            unique_key: r.unique_key,
+            schedule: r.schedule,
+        },
+    });
+    Ok(job)
+}
+
+async fn delete_job(
+    transaction: &mut Transaction<'_, Postgres>,
+    job_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(r#"DELETE FROM background_jobs WHERE job_id = $1"#, job_id)
+        .execute(transaction)
+        .await
+        .context("Failed to delete a job")?;
+    Ok(())
+}
+
+async fn retry_later(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &QueuedJob,
+    error: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    let delay = Duration::seconds(FIRST_RETRY_DELAY_SECONDS << job.n_attempts.min(16));
+    sqlx::query!(
+        r#"
+UPDATE background_jobs
+SET n_attempts = n_attempts + 1, run_at = $2, last_error = $3
+WHERE job_id = $1
+"#,
+        job.job_id,
+        Utc::now() + delay,
+        error.to_string(),
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to schedule a retry")?;
+    Ok(())
+}
+
+/// Queue the next run of a recurring job, whether this one succeeded or not.
+async fn queue_next_run(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &JobRow,
+) -> Result<(), anyhow::Error> {
+    let schedule = match &job.schedule {
+        Some(schedule) => schedule,
+        None => return Ok(()),
+    };
+    let next_run = Schedule::parse(schedule)
+        .map_err(anyhow::Error::msg)
+        .and_then(|s| {
+            s.next_after(Utc::now())
+                .context("The schedule has no run left")
+        });
+    match next_run {
+        Ok(run_at) => {
+            insert_job(transaction, job, run_at).await?;
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                schedule,
+                "Failed to schedule the next run of a recurring job",
+            );
+        }
+    }
+    Ok(())
+}
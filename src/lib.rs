@@ -3,12 +3,24 @@ extern crate proptest;
 
 pub mod authentication;
 pub mod configuration;
+pub mod consent;
+pub mod data_subject;
 pub mod domain;
 pub mod email_client;
+pub mod email_html;
+pub mod email_outbox;
+pub mod email_template;
+pub mod export;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_search;
+pub mod jobs;
+pub mod markdown;
+pub mod pending_subscriptions;
 pub mod routes;
+pub mod security_headers;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_import;
 pub mod telemetry;
 pub mod utils;
@@ -2,8 +2,8 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use tokio::task::JoinError;
 use zero2prod::{
-    configuration::get_configuration, issue_delivery_worker::run_worker_until_stopped,
-    startup::Application, telemetry,
+    configuration::get_configuration, jobs::run_worker_until_stopped, startup::Application,
+    telemetry,
 };
 
 /// Main app, will panic if no configuration file is found
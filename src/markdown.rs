@@ -0,0 +1,165 @@
+//! Rendering of newsletter issues authored in Markdown.
+use pulldown_cmark::{html::push_html, Event, HeadingLevel, LinkType, Options, Parser, Tag};
+
+/// The two bodies of an email, generated from the same Markdown source.
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_markdown(markdown: &str) -> RenderedMarkdown {
+    RenderedMarkdown {
+        html: render_html(markdown),
+        text: render_text(markdown),
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS,
+    )
+}
+
+// Markdown allows raw HTML: it must go through `email_html::process_issue_html`
+// before being stored.
+fn render_html(markdown: &str) -> String {
+    let mut html = String::new();
+    push_html(&mut html, parser(markdown));
+    html
+}
+
+fn render_text(markdown: &str) -> String {
+    // Nested blocks (list items, quotes...) are rendered in their own buffer,
+    // then indented or prefixed when they are closed.
+    let mut blocks = vec![String::new()];
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+
+    for event in parser(markdown) {
+        let current = blocks.last_mut().unwrap();
+        match event {
+            Event::Start(Tag::Heading(..) | Tag::BlockQuote | Tag::CodeBlock(_) | Tag::Item) => {
+                blocks.push(String::new())
+            }
+            Event::Start(Tag::List(start)) => lists.push(start),
+            Event::Start(Tag::Link(link_type, destination, _)) => {
+                // Autolinks already display their destination.
+                links.push(match link_type {
+                    LinkType::Autolink | LinkType::Email => String::new(),
+                    _ => destination.to_string(),
+                });
+            }
+            Event::Start(Tag::Image(_, destination, _)) => links.push(destination.to_string()),
+            Event::End(Tag::Link(..) | Tag::Image(..)) => {
+                let destination = links.pop().unwrap_or_default();
+                if !destination.is_empty() {
+                    current.push_str(&format!(" ({})", destination));
+                }
+            }
+            Event::End(Tag::Paragraph) => current.push_str("\n\n"),
+            Event::End(Tag::Heading(level, ..)) => {
+                let heading = blocks.pop().unwrap();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                let parent = blocks.last_mut().unwrap();
+                parent.push_str(&heading);
+                parent.push('\n');
+                parent.push_str(&underline.repeat(heading.chars().count()));
+                parent.push_str("\n\n");
+            }
+            Event::End(Tag::BlockQuote) => {
+                let quote = blocks.pop().unwrap();
+                let parent = blocks.last_mut().unwrap();
+                for line in quote.trim_end().lines() {
+                    parent.push_str(format!("> {}", line).trim_end());
+                    parent.push('\n');
+                }
+                parent.push('\n');
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                let code = blocks.pop().unwrap();
+                let parent = blocks.last_mut().unwrap();
+                for line in code.trim_end().lines() {
+                    parent.push_str(format!("    {}", line).trim_end());
+                    parent.push('\n');
+                }
+                parent.push('\n');
+            }
+            Event::End(Tag::Item) => {
+                let item = blocks.pop().unwrap();
+                let marker = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                let indentation = format!("\n{}", " ".repeat(marker.len()));
+                let parent = blocks.last_mut().unwrap();
+                parent.push_str(&marker);
+                parent.push_str(&item.trim_end().replace('\n', &indentation));
+                parent.push('\n');
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    current.push('\n');
+                }
+            }
+            Event::Text(text) | Event::Code(text) => current.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => current.push('\n'),
+            Event::Rule => current.push_str("----\n\n"),
+            Event::TaskListMarker(checked) => {
+                current.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            _ => {}
+        }
+    }
+
+    let mut text = blocks.pop().unwrap().trim_end().to_owned();
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let rendered =
+            render_markdown("# Hello\n\nSome *emphasis* and a [link](https://example.com).");
+        assert!(rendered.html.contains("<h1>Hello</h1>"));
+        assert!(rendered.html.contains("<em>emphasis</em>"));
+        assert!(rendered.html.contains(r#"<a href="https://example.com""#));
+    }
+
+    #[test]
+    fn plain_text_is_readable() {
+        let rendered = render_markdown(
+            "# Hello\n\nSome **bold** text and a [link](https://example.com).\n\n\
+            - first\n- second\n\n1. one\n2. two\n\n> A quote\n",
+        );
+        assert_eq!(
+            rendered.text,
+            "Hello\n=====\n\n\
+            Some bold text and a link (https://example.com).\n\n\
+            - first\n- second\n\n\
+            1. one\n2. two\n\n\
+            > A quote\n"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_not_repeated_in_plain_text() {
+        let rendered = render_markdown("Visit <https://example.com>");
+        assert_eq!(rendered.text, "Visit https://example.com\n");
+    }
+
+    #[test]
+    fn template_placeholders_are_preserved() {
+        let rendered = render_markdown("Hi {{ name }}!");
+        assert!(rendered.html.contains("Hi {{ name }}!"));
+        assert_eq!(rendered.text, "Hi {{ name }}!\n");
+    }
+}
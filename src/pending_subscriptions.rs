@@ -0,0 +1,253 @@
+//! Subscriptions that are never confirmed: their owner gets one reminder,
+//! then they are deleted, not to keep personal data nobody consented to.
+//!
+//! The consent events of people who never opted in are deleted along with
+//! them. Those who confirmed a subscription at some point keep theirs, as
+//! for subscribers deleted by an admin.
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    configuration::PendingSubscriptionsSettings,
+    consent::ConsentAction,
+    domain::{SubscriberEmail, SubscriptionStatus},
+    email_client::EmailClient,
+    email_template::{EmailTemplates, MergeFields},
+    jobs::{ExecutionOutcome, Job, JobContext, Lane},
+    routes::{
+        confirmation_url, generate_subscription_token, store_token, CONFIRMATION_TOKEN_TTL_HOURS,
+    },
+};
+
+/// Send the reminders that are due. Runs every `check_interval_minutes`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SendPendingReminders;
+
+impl Job for SendPendingReminders {
+    const KIND: &'static str = "send_pending_subscription_reminders";
+    // A whole batch, that must not hold up a transactional worker.
+    const LANE: Lane = Lane::Bulk;
+    const PRIORITY: i16 = -10;
+
+    async fn run(
+        self,
+        _transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        // Reminders are committed one by one: a failure leaves the others
+        // for the next run. Like confirmation emails, they are about a
+        // single subscription and go through the transactional stream.
+        while let ExecutionOutcome::TaskCompleted = try_send_reminder(
+            &context.pool,
+            context.email_client(Lane::Transactional),
+            &context.base_url,
+            &context.pending_subscriptions,
+        )
+        .await?
+        {}
+        Ok(())
+    }
+}
+
+/// Runs on `purge_schedule`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PurgeStalePendingSubscriptions;
+
+impl Job for PurgeStalePendingSubscriptions {
+    const KIND: &'static str = "purge_stale_pending_subscriptions";
+    const LANE: Lane = Lane::Bulk;
+    const PRIORITY: i16 = -10;
+
+    async fn run(
+        self,
+        _transaction: &mut Transaction<'_, Postgres>,
+        context: &JobContext,
+    ) -> Result<(), anyhow::Error> {
+        purge_stale_pending_subscriptions(&context.pool, &context.pending_subscriptions).await?;
+        Ok(())
+    }
+}
+
+/// Remind one subscriber who has not confirmed their subscription in time,
+/// with a new confirmation link.
+#[tracing::instrument(skip_all, fields(subscriber_id = tracing::field::Empty), err)]
+pub async fn try_send_reminder(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &PendingSubscriptionsSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let now = Utc::now();
+    let mut transaction = pool.begin().await?;
+    // Those past the retention period are about to be purged instead.
+    let subscriber = sqlx::query!(
+        r#"
+SELECT id, email, name
+FROM subscriptions
+WHERE status = $1 AND reminder_sent_at IS NULL AND pending_since < $2 AND pending_since >= $3
+ORDER BY pending_since
+LIMIT 1
+FOR UPDATE
+SKIP LOCKED
+"#,
+        SubscriptionStatus::PendingConfirmation.as_str(),
+        now - settings.reminder_delay(),
+        now - settings.retention(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look for a subscriber to remind")?;
+    let subscriber = match subscriber {
+        Some(s) => s,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("subscriber_id", display(subscriber.id));
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET reminder_sent_at = $2 WHERE id = $1"#,
+        subscriber.id,
+        now,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record a reminder")?;
+    match SubscriberEmail::parse(subscriber.email) {
+        Ok(email) => {
+            let token = generate_subscription_token();
+            store_token(&token, &subscriber.id, &mut transaction)
+                .await
+                .context("Failed to store the confirmation token of a reminder")?;
+            // Like newsletter deliveries, reminders are not retried: a
+            // failing address would otherwise hold back the others.
+            if let Err(e) = send_reminder_email(
+                email_client,
+                &email,
+                &subscriber.name,
+                &confirmation_url(base_url, &token),
+                settings.retention_days,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a reminder",
+                );
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.message = %e,
+                "Skipping a subscriber to remind. Their stored details are invalid",
+            );
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit a reminder")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn send_reminder_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    name: &str,
+    confirmation_link: &str,
+    retention_days: u64,
+) -> Result<(), anyhow::Error> {
+    let plain_body = format!(
+        "Hello {{{{ name }}}}!\nYou subscribed to our newsletter, but have not confirmed \
+        your subscription yet.\nVisit {} to confirm it. The link expires in {} hours.\n\
+        Without a confirmation, your address will be deleted {} days after you subscribed.",
+        confirmation_link, CONFIRMATION_TOKEN_TTL_HOURS, retention_days
+    );
+    let html_body = format!(
+        "Hello {{{{ name }}}}!<br />You subscribed to our newsletter, but have not confirmed \
+        your subscription yet.<br />Click <a href=\"{}\">here</a> to confirm it. \
+        The link expires in {} hours.<br />\
+        Without a confirmation, your address will be deleted {} days after you subscribed.",
+        confirmation_link, CONFIRMATION_TOKEN_TTL_HOURS, retention_days
+    );
+    let message =
+        EmailTemplates::parse("Please confirm your subscription", &html_body, &plain_body)?
+            .render(&MergeFields {
+                name,
+                email: email.as_ref(),
+                // Subscribers can only unsubscribe once they are confirmed.
+                unsubscribe_url: None,
+            })?;
+    email_client
+        .send_email(
+            email,
+            &message.subject,
+            &message.html_body,
+            &message.text_body,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Delete the subscriptions still pending after the retention period, along
+/// with their confirmation tokens and, for those who never confirmed a
+/// subscription, their consent events. Returns how many were deleted.
+#[tracing::instrument(skip_all, fields(n_purged = tracing::field::Empty), err)]
+pub async fn purge_stale_pending_subscriptions(
+    pool: &PgPool,
+    settings: &PendingSubscriptionsSettings,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Locked, for confirmations in progress to either complete first or fail.
+    let ids: Vec<Uuid> = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE status = $1 AND pending_since < $2 FOR UPDATE"#,
+        SubscriptionStatus::PendingConfirmation.as_str(),
+        Utc::now() - settings.retention(),
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to look for stale pending subscriptions")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)"#,
+        &ids[..]
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the tokens of stale pending subscriptions")?;
+    // Those who never opted in leave no consent to keep proof of, only their
+    // IP address and user agent: erased like on request. Those who confirmed
+    // a subscription before, then unsubscribed and came back, keep theirs.
+    sqlx::query!(r#"SELECT set_config('zero2prod.erasing', 'on', true)"#)
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to allow the deletion of consent events")?;
+    sqlx::query!(
+        r#"
+DELETE FROM consent_events
+WHERE subscriber_id = ANY($1) AND subscriber_id NOT IN (
+    SELECT subscriber_id FROM consent_events WHERE subscriber_id = ANY($1) AND action = $2
+)
+"#,
+        &ids[..],
+        ConsentAction::Confirmed.as_str(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the consent events of stale pending subscriptions")?;
+    let n_purged = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = ANY($1)"#, &ids[..])
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete stale pending subscriptions")?
+        .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the purge of stale pending subscriptions")?;
+    Span::current().record("n_purged", n_purged);
+    Ok(n_purged)
+}
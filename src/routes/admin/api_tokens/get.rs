@@ -0,0 +1,34 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{list_api_tokens, ApiToken, ApiTokenScope, CsrfToken, UserId},
+    utils::{e500, html_page},
+};
+
+#[derive(Template)]
+#[template(path = "admin/api_tokens.html")]
+struct ApiTokensTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+    tokens: Vec<ApiToken>,
+    scopes: [ApiTokenScope; 2],
+}
+
+pub async fn api_tokens_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tokens = list_api_tokens(&pool, **user_id).await.map_err(e500)?;
+
+    html_page(&ApiTokensTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+        tokens,
+        scopes: ApiTokenScope::ALL,
+    })
+}
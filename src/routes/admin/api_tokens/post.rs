@@ -0,0 +1,103 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{ApiTokenScope, CsrfToken, UserId},
+    utils::{e500, html_page, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    name: String,
+    #[serde(rename = "issues:read")]
+    read_issues: Option<String>,
+    #[serde(rename = "issues:write")]
+    publish_issues: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/api_token_created.html")]
+struct ApiTokenCreatedTemplate {
+    csrf_token: CsrfToken,
+    name: String,
+    token: String,
+}
+
+#[tracing::instrument(
+name = "Create an API token",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn create_api_token(
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let CreateFormData {
+        name,
+        read_issues,
+        publish_issues,
+    } = form.0;
+
+    let name = name.trim();
+    if name.is_empty() || name.graphemes(true).count() > 100 {
+        FlashMessage::error("The token name must be between 1 and 100 characters long").send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+
+    let mut scopes = Vec::new();
+    if read_issues.is_some() {
+        scopes.push(ApiTokenScope::ReadIssues);
+    }
+    if publish_issues.is_some() {
+        scopes.push(ApiTokenScope::PublishIssues);
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("Select at least one scope").send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+
+    let token = crate::authentication::create_api_token(&pool, **user_id, name, &scopes)
+        .await
+        .map_err(e500)?;
+
+    // The token is rendered right away instead of going through a redirect and a
+    // flash message: it must never end up in a cookie.
+    html_page(&ApiTokenCreatedTemplate {
+        csrf_token: csrf_token.into_inner(),
+        name: name.to_owned(),
+        token: token.expose_secret().to_owned(),
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    token_id: Uuid,
+}
+
+#[tracing::instrument(
+name = "Revoke an API token",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn revoke_api_token(
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if crate::authentication::revoke_api_token(&pool, **user_id, form.0.token_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::success("The API token has been revoked".to_string()).send();
+    } else {
+        FlashMessage::error("Unknown API token".to_string()).send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
@@ -1,37 +1,33 @@
-use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web::{web, HttpResponse};
 use anyhow::Context;
+use askama::Template;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, utils::e500};
+use crate::{
+    authentication::{CsrfToken, UserId},
+    utils::{e500, html_page},
+};
+
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardTemplate {
+    csrf_token: CsrfToken,
+    username: String,
+}
 
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Admin dashboard</title>
-</head>
-<body>
-    <p>Welcome {username}!</p>
-    <p>Available actions:</p>
-    <ol>
-        <li><a href="/admin/change_password">Change password</a></li>
-        <li><a href="/admin/newsletter">Issue a newsletter</a></li>
-        <p><a href="/admin/logout">&lt;- Logout</a></p>
-    </ol>
-</body>
-</html>"#,
-        )))
+    html_page(&DashboardTemplate {
+        csrf_token: csrf_token.into_inner(),
+        username,
+    })
 }
 
 #[tracing::instrument(name = "Get username", skip(pool))]
@@ -0,0 +1,85 @@
+use actix_web::{web, web::Bytes, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, TryStreamExt};
+use sqlx::PgPool;
+
+use crate::{
+    export::{export_response, ExportFormat, RowEncoder, CHUNK_SIZE_BYTES},
+    utils::{e400, parse_date_range},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    format: ExportFormat,
+    /// Dates, as `YYYY-MM-DD`. Both ends are inclusive.
+    #[serde(default)]
+    attempted_from: String,
+    #[serde(default)]
+    attempted_until: String,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedDelivery {
+    newsletter_issue_id: String,
+    issue_title: String,
+    subscriber_email: String,
+    outcome: String,
+    attempted_at: String,
+}
+
+/// Stream the outcome of every delivery attempt, oldest first.
+pub async fn export_deliveries(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (attempted_from, attempted_before) =
+        parse_date_range(&parameters.attempted_from, &parameters.attempted_until).map_err(e400)?;
+    let format = parameters.format;
+    Ok(export_response(
+        format,
+        "deliveries",
+        delivery_rows(
+            pool.get_ref().clone(),
+            attempted_from,
+            attempted_before,
+            format,
+        ),
+    ))
+}
+
+fn delivery_rows(
+    pool: PgPool,
+    attempted_from: Option<DateTime<Utc>>,
+    attempted_before: Option<DateTime<Utc>>,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, anyhow::Error>> {
+    async_stream::try_stream! {
+        let mut encoder = RowEncoder::new(format);
+        let mut rows = sqlx::query!(
+            r#"
+SELECT l.newsletter_issue_id, i.title, l.subscriber_email, l.outcome, l.attempted_at
+FROM issue_delivery_log l
+JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+WHERE ($1::timestamptz IS NULL OR l.attempted_at >= $1)
+AND ($2::timestamptz IS NULL OR l.attempted_at < $2)
+ORDER BY l.attempted_at
+"#,
+            attempted_from,
+            attempted_before,
+        )
+        .fetch(&pool);
+        while let Some(row) = rows.try_next().await? {
+            encoder.push(&ExportedDelivery {
+                newsletter_issue_id: row.newsletter_issue_id.to_string(),
+                issue_title: row.title,
+                subscriber_email: row.subscriber_email,
+                outcome: row.outcome,
+                attempted_at: row.attempted_at.to_rfc3339(),
+            })?;
+            if encoder.buffered() >= CHUNK_SIZE_BYTES {
+                yield encoder.take();
+            }
+        }
+        yield encoder.take();
+    }
+}
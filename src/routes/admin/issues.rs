@@ -0,0 +1,35 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::CsrfToken,
+    issue_search::{search_issues, IssuePage, SearchParameters, Visibility},
+    utils::{e500, html_page},
+};
+
+#[derive(Template)]
+#[template(path = "admin/issues.html")]
+struct IssuesTemplate {
+    csrf_token: CsrfToken,
+    query: String,
+    results: IssuePage,
+}
+
+pub async fn issues_list(
+    parameters: web::Query<SearchParameters>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let results = search_issues(&pool, &parameters, Visibility::All)
+        .await
+        .context("Failed to search the newsletter issues")
+        .map_err(e500)?;
+
+    html_page(&IssuesTemplate {
+        csrf_token: csrf_token.into_inner(),
+        query: parameters.0.q,
+        results,
+    })
+}
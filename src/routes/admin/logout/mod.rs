@@ -1,3 +1,3 @@
-pub use get::logout;
+pub use post::logout;
 
-mod get;
+mod post;
@@ -0,0 +1,28 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[tracing::instrument(
+skip(session, pool),
+fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn logout(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_user_session(&pool, **user_id, session_id)
+            .await
+            .map_err(e500)?;
+    }
+    session.logout();
+    FlashMessage::success("Successfully logged out".to_string()).send();
+    Ok(see_other("/login"))
+}
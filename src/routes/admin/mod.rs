@@ -1,11 +1,28 @@
+pub use api_tokens::{api_tokens_form, create_api_token, revoke_api_token};
 pub use dashboard::admin_dashboard;
+pub use deliveries::export_deliveries;
+pub use issues::issues_list;
 pub use logout::logout;
 pub use newsletter::issue_newsletter;
 pub use newsletter::issue_newsletter_form;
+pub use newsletter::preview_newsletter;
+pub use newsletter::{enqueue_delivery_tasks, insert_newsletter_issue};
 pub use password::change_password;
 pub use password::change_password_form;
+pub use sessions::{active_sessions, logout_everywhere, revoke_session};
+pub use subscribers::{
+    consent_history, delete_subscriber, erase_subscriber, export_consent_events,
+    export_subscriber_data, export_subscribers, import_subscribers, import_subscribers_form,
+    resend_confirmation, subscriber_import_report, subscriber_import_result, subscribers_list,
+    unsubscribe_subscriber,
+};
 
+mod api_tokens;
 mod dashboard;
+mod deliveries;
+mod issues;
 mod logout;
 mod newsletter;
 mod password;
+mod sessions;
+mod subscribers;
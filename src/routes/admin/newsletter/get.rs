@@ -1,61 +1,42 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
+use uuid::Uuid;
+
+use crate::{authentication::CsrfToken, email_template::RenderedEmail, utils::html_page};
+
+#[derive(Template)]
+#[template(path = "admin/newsletter.html")]
+pub(super) struct IssueNewsletterTemplate {
+    pub(super) flash_messages: IncomingFlashMessages,
+    pub(super) csrf_token: CsrfToken,
+    pub(super) idempotency_key: String,
+    pub(super) title: String,
+    pub(super) content_markdown: String,
+    pub(super) is_public: bool,
+    pub(super) preview_name: &'static str,
+    pub(super) preview: Option<RenderedEmail>,
+    pub(super) preview_error: Option<String>,
+    pub(super) warnings: Vec<String>,
+    /// Submitted back to confirm the warnings, see `FormData::confirm_warnings`.
+    pub(super) content_digest: String,
+}
 
 pub async fn issue_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut messages_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(messages_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-
-    let idempotency_key = uuid::Uuid::new_v4();
-
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Issue newsletter</title>
-</head>
-<body>
-    {}
-    <form action="/admin/newsletter" method="post">
-        <label>Title
-            <input
-            type="text"
-            placeholder="Enter newsletter title"
-            name="title"
-            >
-        </label>
-        <br>
-
-        <label>Content
-            <input
-            type="text"
-            placeholder="Enter newsletter content"
-            name="content_text"
-            >
-        </label>
-        <br>
-
-        <label>Content HTML
-            <input
-            type="text"
-            placeholder="Enter newsletter content"
-            name="content_html"
-            >
-        </label>
-        <br>
-        <input hidden type="text" name="idempotency_key" value="{}">
-        <button type="submit">Issue newsletter</button>
-    </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-            messages_html, idempotency_key
-        )))
+    html_page(&IssueNewsletterTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+        idempotency_key: Uuid::new_v4().to_string(),
+        title: String::new(),
+        content_markdown: String::new(),
+        is_public: false,
+        preview_name: "",
+        preview: None,
+        preview_error: None,
+        warnings: Vec::new(),
+        content_digest: String::new(),
+    })
 }
@@ -1,5 +1,7 @@
 pub use get::issue_newsletter_form;
-pub use post::issue_newsletter;
+pub use post::{enqueue_delivery_tasks, insert_newsletter_issue, issue_newsletter};
+pub use preview::preview_newsletter;
 
 mod get;
 mod post;
+mod preview;
@@ -1,23 +1,60 @@
 use actix_web::HttpResponse;
-use actix_web::{http::StatusCode, web::ReqData};
-use actix_web_flash_messages::FlashMessage;
+use actix_web::{web, web::ReqData};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use anyhow::Context;
+use sha2::{Digest, Sha256};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::preview::preview_page;
 use crate::{
-    authentication::UserId,
+    authentication::{CsrfToken, UserId},
+    domain::IssueSlug,
+    email_html::process_issue_html,
+    email_template::EmailTemplates,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
-    routes::error_chain_fmt,
+    issue_delivery_worker::enqueue_deliveries,
+    markdown::render_markdown,
+    startup::ApplicationBaseUrl,
     utils::{e400, e500, see_other},
 };
 
 #[derive(serde::Deserialize, std::fmt::Debug)]
 pub struct FormData {
-    title: String,
-    content_html: String,
-    content_text: String,
-    idempotency_key: String,
+    pub(super) title: String,
+    pub(super) content_markdown: String,
+    pub(super) idempotency_key: String,
+    /// Whether the issue is listed in the public archive.
+    #[serde(default)]
+    pub(super) is_public: bool,
+    /// The digest of the content the editor was shown warnings about. Set
+    /// once they have seen them: edited content has to be reviewed again.
+    #[serde(default)]
+    pub(super) confirm_warnings: Option<String>,
+}
+
+/// The bodies of an issue, as they will be stored and delivered.
+pub(super) struct IssueContent {
+    pub(super) html: String,
+    pub(super) text: String,
+    pub(super) warnings: Vec<String>,
+}
+
+impl IssueContent {
+    /// A digest of the processed HTML, which the warnings are about.
+    pub(super) fn digest(&self) -> String {
+        format!("{:x}", Sha256::digest(self.html.as_bytes()))
+    }
+}
+
+pub(super) fn render_issue_content(content_markdown: &str) -> Result<IssueContent, anyhow::Error> {
+    let rendered = render_markdown(content_markdown);
+    let processed = process_issue_html(&rendered.html)?;
+    Ok(IssueContent {
+        html: processed.html,
+        text: rendered.text,
+        warnings: processed.warnings,
+    })
 }
 
 #[tracing::instrument(
@@ -26,34 +63,62 @@ skip_all,
 fields(user_id = % & * user_id)
 )]
 pub async fn issue_newsletter(
-    form: actix_web::web::Form<FormData>,
-    pool: actix_web::web::Data<PgPool>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
     user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+    base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let content = render_issue_content(&form.content_markdown).map_err(e400)?;
+    let warnings_confirmed = form.confirm_warnings.as_deref() == Some(content.digest().as_str());
+    if !content.warnings.is_empty() && !warnings_confirmed {
+        return preview_page(
+            form.0,
+            content,
+            flash_messages,
+            csrf_token.into_inner(),
+            &base_url.0,
+        );
+    }
     let FormData {
         title,
-        content_text,
-        content_html,
+        content_markdown,
         idempotency_key,
+        is_public,
+        ..
     } = form.0;
     let user_id = user_id.into_inner();
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    // Catch typos in placeholders now, rather than when emails go out.
+    if let Err(e) = EmailTemplates::parse(&title, &content.html, &content.text) {
+        FlashMessage::error(e.to_string()).send();
+        return Ok(see_other("/admin/newsletter"));
+    }
 
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
-        NextAction::StartProcessing(t) => t,
+        NextAction::StartProcessing(t) => *t,
         NextAction::ReturnSavedResponse(saved_response) => {
             success_message().send();
             return Ok(saved_response);
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content_text, &content_html)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content.text,
+        &content.html,
+        Some(&content_markdown),
+        is_public,
+        *user_id,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
@@ -70,13 +135,17 @@ pub async fn issue_newsletter(
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
+    markdown_content: Option<&str>,
+    is_public: bool,
+    author_user_id: Uuid,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let slug = IssueSlug::new(title, newsletter_issue_id);
     sqlx::query!(
         r#"
 INSERT INTO newsletter_issues (
@@ -84,14 +153,25 @@ INSERT INTO newsletter_issues (
     title,
     text_content,
     html_content,
-    published_at
+    markdown_content,
+    is_public,
+    slug,
+    author_user_id,
+    status,
+    published_at,
+    created_at,
+    updated_at
 )
-VALUES ($1, $2, $3, $4, now())
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'delivering', now(), now(), now())
 "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        markdown_content,
+        is_public,
+        slug.as_ref(),
+        author_user_id,
     )
     .execute(transaction)
     .await?;
@@ -99,19 +179,28 @@ VALUES ($1, $2, $3, $4, now())
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let n_tasks = enqueue_deliveries(transaction, newsletter_issue_id).await?;
+    // There is nothing for the delivery workers to do without subscribers.
+    if n_tasks == 0 {
+        mark_issue_as_delivered(transaction, newsletter_issue_id).await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn mark_issue_as_delivered(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-INSERT INTO issue_delivery_queue (
-    newsletter_issue_id,
-    subscriber_email
-)
-SELECT $1, email
-FROM subscriptions
-WHERE status = 'confirmed'
+UPDATE newsletter_issues
+SET status = 'delivered', updated_at = now()
+WHERE newsletter_issue_id = $1
 "#,
         newsletter_issue_id,
     )
@@ -126,28 +215,3 @@ fn success_message() -> FlashMessage {
         emails will go out shortly.",
     )
 }
-
-#[derive(thiserror::Error)]
-pub enum PublishError {
-    #[error("Authentication failed.")]
-    AuthError(#[source] anyhow::Error),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl std::fmt::Debug for PublishError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
-impl actix_web::ResponseError for PublishError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            PublishError::UnexpectedError(_) => {
-                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-            PublishError::AuthError(_) => HttpResponse::new(StatusCode::UNAUTHORIZED),
-        }
-    }
-}
@@ -0,0 +1,81 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use super::get::IssueNewsletterTemplate;
+use super::post::{render_issue_content, FormData, IssueContent};
+use crate::{
+    authentication::CsrfToken,
+    email_template::{EmailTemplates, MergeFields},
+    routes::unsubscribe_url,
+    security_headers::AllowInlineStyles,
+    startup::ApplicationBaseUrl,
+    utils::{e400, html_page},
+};
+
+const PREVIEW_NAME: &str = "Jane Doe";
+const PREVIEW_EMAIL: &str = "jane.doe@example.com";
+
+/// Render the issue the way the delivery worker will, for a made-up subscriber.
+///
+/// The form is displayed again, pre-filled, so that the editor can keep working
+/// on the issue or publish it.
+pub async fn preview_newsletter(
+    form: web::Form<FormData>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let content = render_issue_content(&form.content_markdown).map_err(e400)?;
+    preview_page(
+        form.0,
+        content,
+        flash_messages,
+        csrf_token.into_inner(),
+        &base_url.0,
+    )
+}
+
+pub(super) fn preview_page(
+    form: FormData,
+    content: IssueContent,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+    base_url: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        title,
+        content_markdown,
+        idempotency_key,
+        is_public,
+        ..
+    } = form;
+    let unsubscribe_url = unsubscribe_url(base_url, "preview");
+    let rendered = EmailTemplates::parse(&title, &content.html, &content.text).and_then(|t| {
+        t.render(&MergeFields {
+            name: PREVIEW_NAME,
+            email: PREVIEW_EMAIL,
+            unsubscribe_url: Some(&unsubscribe_url),
+        })
+    });
+    let (preview, preview_error) = match rendered {
+        Ok(email) => (Some(email), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let mut response = html_page(&IssueNewsletterTemplate {
+        flash_messages,
+        csrf_token,
+        idempotency_key,
+        title,
+        content_markdown,
+        is_public,
+        preview_name: PREVIEW_NAME,
+        preview,
+        preview_error,
+        content_digest: content.digest(),
+        warnings: content.warnings,
+    })?;
+    // Emails are styled inline, and the preview inherits the policy of the page.
+    response.extensions_mut().insert(AllowInlineStyles);
+    Ok(response)
+}
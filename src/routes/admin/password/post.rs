@@ -8,9 +8,10 @@ use sqlx::PgPool;
 
 use crate::authentication::UserId;
 use crate::{
-    authentication::{verify_password_hash, AuthError},
+    authentication::{revoke_other_user_sessions, verify_password_hash, AuthError},
     domain::AdminPassword,
     routes::error_chain_fmt,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 
@@ -22,13 +23,14 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-skip(form, pool),
+skip(form, pool, session),
 fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
 )]
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
@@ -64,13 +66,21 @@ pub async fn change_password(
         AuthError::UnexpectedError(_) => ChangePasswordError::UnexpectedError(e.into()),
     })?;
 
-    match crate::authentication::update_password(*user_id, new_password.as_ref(), &pool).await {
-        Ok(_) => {
-            FlashMessage::success("Successfully changed password".to_string()).send();
-            Ok(see_other("/admin/change_password"))
-        }
-        Err(e) => Err(e500(e)),
-    }
+    crate::authentication::update_password(*user_id, new_password.as_ref(), &pool)
+        .await
+        .map_err(e500)?;
+
+    // Anybody who got hold of the old password is logged out everywhere else.
+    let session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session has no identifier"))?;
+    revoke_other_user_sessions(&pool, *user_id, session_id)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::success("Successfully changed password".to_string()).send();
+    Ok(see_other("/admin/change_password"))
 }
 
 #[derive(thiserror::Error)]
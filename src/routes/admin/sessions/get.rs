@@ -0,0 +1,48 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{list_user_sessions, CsrfToken, UserId, UserSession},
+    configuration::SessionSettings,
+    session_state::TypedSession,
+    utils::{e500, html_page},
+};
+
+#[derive(Template)]
+#[template(path = "admin/sessions.html")]
+struct ActiveSessionsTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+    current_session_id: Option<Uuid>,
+    sessions: Vec<UserSession>,
+}
+
+impl ActiveSessionsTemplate {
+    fn is_current(&self, session: &UserSession) -> bool {
+        self.current_session_id == Some(session.session_id)
+    }
+}
+
+pub async fn active_sessions(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    csrf_token: web::ReqData<CsrfToken>,
+    settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = list_user_sessions(&pool, **user_id, &settings)
+        .await
+        .map_err(e500)?;
+
+    html_page(&ActiveSessionsTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+        current_session_id,
+        sessions,
+    })
+}
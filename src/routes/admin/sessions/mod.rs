@@ -0,0 +1,5 @@
+pub use get::active_sessions;
+pub use post::{logout_everywhere, revoke_session};
+
+mod get;
+mod post;
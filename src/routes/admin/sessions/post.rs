@@ -0,0 +1,54 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{revoke_all_user_sessions, revoke_user_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(
+name = "Revoke a session",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn revoke_session(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_user_session(&pool, **user_id, form.0.session_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::success("The session has been revoked".to_string()).send();
+    } else {
+        FlashMessage::error("Unknown session".to_string()).send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(
+name = "Log out everywhere",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn logout_everywhere(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    revoke_all_user_sessions(&pool, **user_id)
+        .await
+        .map_err(e500)?;
+    session.logout();
+    FlashMessage::success("Successfully logged out of every session".to_string()).send();
+    Ok(see_other("/login"))
+}
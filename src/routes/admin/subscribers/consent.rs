@@ -0,0 +1,127 @@
+use actix_web::{web, web::Bytes, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use futures_util::{Stream, TryStreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::CsrfToken,
+    consent::{list_consent_events, ConsentEvent},
+    export::{export_response, ExportFormat, RowEncoder, CHUNK_SIZE_BYTES},
+    utils::{e500, html_page},
+};
+
+#[derive(Template)]
+#[template(path = "admin/subscriber_consent.html")]
+struct ConsentHistoryTemplate {
+    csrf_token: CsrfToken,
+    subscriber_id: Uuid,
+    email: String,
+    events: Vec<ConsentEvent>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    format: ExportFormat,
+    /// Only export the history of this subscriber.
+    subscriber_id: Option<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedConsentEvent {
+    subscriber_id: String,
+    email: String,
+    action: String,
+    source: String,
+    privacy_policy_version: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    occurred_at: String,
+}
+
+/// The history outlives the subscription: it is shown for deleted subscribers too.
+pub async fn consent_history(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let email = sqlx::query!(
+        r#"
+SELECT email FROM subscriptions WHERE id = $1
+UNION ALL
+SELECT email FROM consent_events WHERE subscriber_id = $1
+LIMIT 1
+"#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the email of a subscriber")
+    .map_err(e500)?;
+    let email = match email.and_then(|r| r.email) {
+        Some(email) => email,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let events = list_consent_events(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    html_page(&ConsentHistoryTemplate {
+        csrf_token: csrf_token.into_inner(),
+        subscriber_id,
+        email,
+        events,
+    })
+}
+
+/// Stream every consent event, oldest first.
+pub async fn export_consent_events(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let format = parameters.format;
+    export_response(
+        format,
+        "consent-events",
+        consent_event_rows(pool.get_ref().clone(), parameters.subscriber_id, format),
+    )
+}
+
+fn consent_event_rows(
+    pool: PgPool,
+    subscriber_id: Option<Uuid>,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, anyhow::Error>> {
+    async_stream::try_stream! {
+        let mut encoder = RowEncoder::new(format);
+        let mut rows = sqlx::query!(
+            r#"
+SELECT
+    subscriber_id, email, action, source, privacy_policy_version,
+    ip_address, user_agent, occurred_at
+FROM consent_events
+WHERE ($1::uuid IS NULL OR subscriber_id = $1)
+ORDER BY occurred_at
+"#,
+            subscriber_id,
+        )
+        .fetch(&pool);
+        while let Some(row) = rows.try_next().await? {
+            encoder.push(&ExportedConsentEvent {
+                subscriber_id: row.subscriber_id.to_string(),
+                email: row.email,
+                action: row.action,
+                source: row.source,
+                privacy_policy_version: row.privacy_policy_version,
+                ip_address: row.ip_address,
+                user_agent: row.user_agent,
+                occurred_at: row.occurred_at.to_rfc3339(),
+            })?;
+            if encoder.buffered() >= CHUNK_SIZE_BYTES {
+                yield encoder.take();
+            }
+        }
+        yield encoder.take();
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::{web, web::Bytes, HttpResponse};
+use futures_util::{Stream, TryStreamExt};
+use sqlx::PgPool;
+
+use super::get::{Filters, SubscriberQuery};
+use crate::{
+    export::{export_response, ExportFormat, RowEncoder, CHUNK_SIZE_BYTES},
+    utils::e400,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    format: ExportFormat,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    id: String,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: String,
+}
+
+/// Stream the subscribers matching the same filters as the subscriber list.
+pub async fn export_subscribers(
+    filters: web::Query<Filters>,
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = filters.parse().map_err(e400)?;
+    let format = parameters.format;
+    Ok(export_response(
+        format,
+        "subscribers",
+        subscriber_rows(pool.get_ref().clone(), query, format),
+    ))
+}
+
+fn subscriber_rows(
+    pool: PgPool,
+    query: SubscriberQuery,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, anyhow::Error>> {
+    async_stream::try_stream! {
+        let mut encoder = RowEncoder::new(format);
+        let mut rows = sqlx::query!(
+            r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE (email ILIKE $1 OR name ILIKE $1)
+AND ($2 = '' OR status = $2)
+AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+ORDER BY subscribed_at
+"#,
+            query.pattern,
+            query.status,
+            query.subscribed_from,
+            query.subscribed_before,
+        )
+        .fetch(&pool);
+        while let Some(row) = rows.try_next().await? {
+            encoder.push(&ExportedSubscriber {
+                id: row.id.to_string(),
+                email: row.email,
+                name: row.name,
+                status: row.status,
+                subscribed_at: row.subscribed_at.to_rfc3339(),
+            })?;
+            if encoder.buffered() >= CHUNK_SIZE_BYTES {
+                yield encoder.take();
+            }
+        }
+        yield encoder.take();
+    }
+}
@@ -0,0 +1,167 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::CsrfToken,
+    domain::SubscriptionStatus,
+    utils::{e400, e500, html_page, parse_date_range},
+};
+
+const PAGE_SIZE: i64 = 50;
+/// Deeper pages are clamped to it, which keeps offsets well within range.
+const MAX_PAGE: i64 = 10_000;
+
+// Empty fields are sent by the filter form as empty strings.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default)]
+#[serde(default)]
+pub struct Filters {
+    /// Matched against both emails and names.
+    q: String,
+    status: String,
+    /// Dates, as `YYYY-MM-DD`. Both ends are inclusive.
+    subscribed_from: String,
+    subscribed_until: String,
+    #[serde(skip_serializing)]
+    page: Option<i64>,
+}
+
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+    filters: Filters,
+    statuses: [SubscriptionStatus; 4],
+    subscribers: Vec<Subscriber>,
+    page: i64,
+    has_next_page: bool,
+}
+
+impl SubscribersTemplate {
+    fn is_selected(&self, status: &SubscriptionStatus) -> bool {
+        self.filters.status == status.as_str()
+    }
+
+    /// Link to another page of results, with the same filters.
+    fn page_url(&self, page: &i64) -> String {
+        let filters = serde_urlencoded::to_string(&self.filters).unwrap_or_default();
+        format!("/admin/subscribers?{}&page={}", filters, page)
+    }
+
+    /// Link to an export of every subscriber matching the filters.
+    fn export_url(&self, format: &str) -> String {
+        let filters = serde_urlencoded::to_string(&self.filters).unwrap_or_default();
+        format!("/admin/subscribers/export?{}&format={}", filters, format)
+    }
+}
+
+impl Filters {
+    /// 1-based, out of range values are clamped.
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+
+    pub(super) fn parse(&self) -> Result<SubscriberQuery, anyhow::Error> {
+        if !self.status.is_empty() {
+            SubscriptionStatus::parse(&self.status).map_err(anyhow::Error::msg)?;
+        }
+        let (subscribed_from, subscribed_before) =
+            parse_date_range(&self.subscribed_from, &self.subscribed_until)?;
+        Ok(SubscriberQuery {
+            pattern: format!("%{}%", escape_like_pattern(self.q.trim())),
+            status: self.status.clone(),
+            subscribed_from,
+            subscribed_before,
+        })
+    }
+}
+
+/// Validated `Filters`, ready to be bound to a query.
+#[derive(Debug)]
+pub(super) struct SubscriberQuery {
+    /// A `LIKE` pattern, matched against both emails and names.
+    pub(super) pattern: String,
+    /// Empty to match any status.
+    pub(super) status: String,
+    pub(super) subscribed_from: Option<DateTime<Utc>>,
+    pub(super) subscribed_before: Option<DateTime<Utc>>,
+}
+
+pub async fn subscribers_list(
+    filters: web::Query<Filters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filters = filters.into_inner();
+    let query = filters.parse().map_err(e400)?;
+    let page = filters.page();
+
+    let mut subscribers = search_subscribers(&pool, &query, page)
+        .await
+        .map_err(e500)?;
+    let has_next_page = subscribers.len() as i64 > PAGE_SIZE;
+    subscribers.truncate(PAGE_SIZE as usize);
+
+    html_page(&SubscribersTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+        filters,
+        statuses: SubscriptionStatus::ALL,
+        subscribers,
+        page,
+        has_next_page,
+    })
+}
+
+/// Returns up to one subscriber more than a page, to know whether there is a next one.
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+async fn search_subscribers(
+    pool: &PgPool,
+    query: &SubscriberQuery,
+    page: i64,
+) -> Result<Vec<Subscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE (email ILIKE $1 OR name ILIKE $1)
+AND ($2 = '' OR status = $2)
+AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+ORDER BY subscribed_at DESC
+LIMIT $5 OFFSET $6
+"#,
+        query.pattern,
+        query.status,
+        query.subscribed_from,
+        query.subscribed_before,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to search subscribers")?;
+    Ok(subscribers)
+}
+
+/// `%` and `_` are wildcards in `LIKE` patterns: searches are for the literal characters.
+fn escape_like_pattern(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,475 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::header::ContentDisposition, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{verify_multipart_csrf_token, CsrfToken, UserId},
+    consent::{record_consent_events, ConsentAction, ConsentContext},
+    data_subject::erased_emails,
+    domain::SubscriptionStatus,
+    email_outbox::ImportConfirmationEmail,
+    jobs::enqueue,
+    routes::generate_subscription_token,
+    startup::{HmacSecret, PrivacyPolicyVersion},
+    subscriber_import::{error_report, parse_csv, ImportMode, ImportedSubscriber, RowError},
+    utils::{e500, html_page, see_other},
+};
+
+/// Large enough for a few hundred thousand subscribers.
+const MAX_FILE_SIZE_BYTES: usize = 20 * 1024 * 1024;
+/// The file, along with the few bytes of the other fields.
+const MAX_FORM_SIZE_BYTES: usize = MAX_FILE_SIZE_BYTES + 64 * 1024;
+/// Rows inserted per transaction.
+const CHUNK_SIZE: usize = 500;
+
+#[derive(Template)]
+#[template(path = "admin/subscribers_import.html")]
+struct ImportFormTemplate {
+    flash_messages: IncomingFlashMessages,
+    csrf_token: CsrfToken,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers_import_result.html")]
+struct ImportResultTemplate {
+    csrf_token: CsrfToken,
+    import_id: Uuid,
+    n_imported: i32,
+    n_errors: i32,
+}
+
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    html_page(&ImportFormTemplate {
+        flash_messages,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
+
+#[tracing::instrument(
+name = "Import subscribers",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    privacy_policy_version: web::Data<PrivacyPolicyVersion>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    verify_multipart_csrf_token(&request, &mut payload).await?;
+    let (file, mode) = match read_form(payload).await? {
+        Ok(form) => form,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+    let parsed = match parse_csv(&file, mode) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(format!("{:#}", e)).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+    let mut errors = parsed.errors;
+    let (to_insert, already_subscribed) = skip_existing_subscribers(&pool, parsed.subscribers)
+        .await
+        .map_err(e500)?;
+    errors.extend(already_subscribed);
+    let (to_insert, erased) = skip_erased_subscribers(&pool, to_insert, &hmac_secret.0)
+        .await
+        .map_err(e500)?;
+    errors.extend(erased);
+    // The admin vouches for the consent of imported subscribers.
+    let consent = ConsentContext::new(&request, "csv_import", &privacy_policy_version.0);
+
+    errors.sort_by_key(|e| e.line);
+    // Saved first: the outcome of the import is there to look at as soon as
+    // the first confirmation emails go out.
+    let import_id = save_import(&pool, **user_id, &errors).await.map_err(e500)?;
+
+    let mut to_insert = to_insert.into_iter().peekable();
+    while to_insert.peek().is_some() {
+        let chunk: Vec<_> = to_insert.by_ref().take(CHUNK_SIZE).collect();
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")
+            .map_err(e500)?;
+        let inserted = insert_subscribers(&mut transaction, chunk, &consent)
+            .await
+            .map_err(e500)?;
+        for (subscriber_id, status) in &inserted.ids {
+            if *status == SubscriptionStatus::PendingConfirmation {
+                let email = ImportConfirmationEmail {
+                    subscriber_id: *subscriber_id,
+                };
+                enqueue(&mut transaction, &email).await.map_err(e500)?;
+            }
+        }
+        record_imported_subscribers(&mut transaction, import_id, inserted.ids.len())
+            .await
+            .map_err(e500)?;
+        if !inserted.conflicts.is_empty() {
+            errors.extend(inserted.conflicts);
+            errors.sort_by_key(|e| e.line);
+            record_import_errors(&mut transaction, import_id, &errors)
+                .await
+                .map_err(e500)?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the imported subscribers")
+            .map_err(e500)?;
+    }
+
+    Ok(see_other(&format!(
+        "/admin/subscribers/imports/{}",
+        import_id
+    )))
+}
+
+/// The uploaded file and the chosen import mode, or a message for the admin.
+async fn read_form(
+    mut payload: Multipart,
+) -> Result<Result<(Vec<u8>, ImportMode), String>, actix_web::Error> {
+    let mut file = None;
+    let mut mode = None;
+    let mut form_size = 0;
+    while let Some(mut field) = payload.try_next().await? {
+        let name = field.name().map(str::to_owned);
+        let mut content = Vec::new();
+        while let Some(chunk) = field.try_next().await? {
+            form_size += chunk.len();
+            let error = if form_size > MAX_FORM_SIZE_BYTES {
+                Some("The form is too large".to_owned())
+            } else if content.len() + chunk.len() > MAX_FILE_SIZE_BYTES {
+                Some(format!(
+                    "The file is too large: the limit is {}MB",
+                    MAX_FILE_SIZE_BYTES / 1024 / 1024
+                ))
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                discard_rest_of_form(field, payload).await?;
+                return Ok(Err(error));
+            }
+            content.extend_from_slice(&chunk);
+        }
+        match name.as_deref() {
+            Some("file") => file = Some(content),
+            Some("mode") => mode = Some(String::from_utf8_lossy(&content).into_owned()),
+            _ => {}
+        }
+    }
+
+    let file = match file {
+        Some(file) if !file.is_empty() => file,
+        _ => return Ok(Err("Select a CSV file to import".into())),
+    };
+    let mode = match ImportMode::parse(mode.as_deref().unwrap_or_default()) {
+        Ok(mode) => mode,
+        Err(e) => return Ok(Err(e)),
+    };
+    Ok(Ok((file, mode)))
+}
+
+/// Read without being kept: the client does not get a response before its
+/// request has been read in full.
+async fn discard_rest_of_form(
+    mut field: Field,
+    mut payload: Multipart,
+) -> Result<(), actix_web::Error> {
+    while field.try_next().await?.is_some() {}
+    // The next field is only read once this one is dropped.
+    drop(field);
+    while let Some(mut field) = payload.try_next().await? {
+        while field.try_next().await?.is_some() {}
+    }
+    Ok(())
+}
+
+/// Subscribers are matched on their email, regardless of its case.
+#[tracing::instrument(skip_all)]
+async fn skip_existing_subscribers(
+    pool: &PgPool,
+    subscribers: Vec<ImportedSubscriber>,
+) -> Result<(Vec<ImportedSubscriber>, Vec<RowError>), anyhow::Error> {
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.email.as_ref().to_lowercase())
+        .collect();
+    let existing: std::collections::HashSet<String> = sqlx::query!(
+        r#"SELECT lower(email) AS "email!" FROM subscriptions WHERE lower(email) = ANY($1)"#,
+        &emails[..]
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to look for existing subscribers")?
+    .into_iter()
+    .map(|r| r.email)
+    .collect();
+
+    let (already_subscribed, to_insert): (Vec<_>, Vec<_>) = subscribers
+        .into_iter()
+        .partition(|s| existing.contains(&s.subscriber.email.as_ref().to_lowercase()));
+    let errors = already_subscribed
+        .iter()
+        .map(already_subscribed_error)
+        .collect();
+    Ok((to_insert, errors))
+}
+
+fn already_subscribed_error(s: &ImportedSubscriber) -> RowError {
+    skipped_row_error(s, "Already subscribed")
+}
+
+/// People who asked for their data to be erased must not be imported again.
+#[tracing::instrument(skip_all)]
+async fn skip_erased_subscribers(
+    pool: &PgPool,
+    subscribers: Vec<ImportedSubscriber>,
+    hmac_secret: &Secret<String>,
+) -> Result<(Vec<ImportedSubscriber>, Vec<RowError>), anyhow::Error> {
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.email.as_ref().to_owned())
+        .collect();
+    let erased = erased_emails(pool, &emails, hmac_secret).await?;
+
+    let (erased, to_insert): (Vec<_>, Vec<_>) = subscribers
+        .into_iter()
+        .partition(|s| erased.contains(&s.subscriber.email.as_ref().to_lowercase()));
+    let errors = erased
+        .iter()
+        .map(|s| skipped_row_error(s, "Erased on request: the address cannot be imported again"))
+        .collect();
+    Ok((to_insert, errors))
+}
+
+fn skipped_row_error(s: &ImportedSubscriber, reason: &str) -> RowError {
+    RowError {
+        line: s.line,
+        email: s.subscriber.email.as_ref().to_owned(),
+        name: s.subscriber.name.as_ref().to_owned(),
+        reason: reason.into(),
+    }
+}
+
+struct InsertedSubscribers {
+    /// Along with their status.
+    ids: Vec<(Uuid, SubscriptionStatus)>,
+    /// Subscribers that someone subscribed with in the meantime.
+    conflicts: Vec<RowError>,
+}
+
+#[tracing::instrument(skip_all, fields(n_subscribers = subscribers.len()))]
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscribers: Vec<ImportedSubscriber>,
+    consent: &ConsentContext,
+) -> Result<InsertedSubscribers, anyhow::Error> {
+    let ids: Vec<Uuid> = subscribers.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.name.as_ref().to_owned())
+        .collect();
+    let statuses: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.status.as_str().to_owned())
+        .collect();
+    let unsubscribe_tokens: Vec<String> = subscribers
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    // Someone may have subscribed with the same email in the meantime.
+    let inserted_ids: std::collections::HashSet<Uuid> = sqlx::query!(
+        r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+SELECT id, email, name, $5, status, unsubscribe_token
+FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $6::text[])
+    AS t(id, email, name, status, unsubscribe_token)
+ON CONFLICT (email) DO NOTHING
+RETURNING id
+"#,
+        &ids[..],
+        &emails[..],
+        &names[..],
+        &statuses[..],
+        Utc::now(),
+        &unsubscribe_tokens[..],
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to insert imported subscribers")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    let (inserted, conflicts): (Vec<_>, Vec<_>) = ids
+        .into_iter()
+        .zip(subscribers)
+        .partition(|(id, _)| inserted_ids.contains(id));
+    let conflicts: Vec<RowError> = conflicts
+        .iter()
+        .map(|(_, s)| already_subscribed_error(s))
+        .collect();
+    let inserted: Vec<(Uuid, SubscriptionStatus)> = inserted
+        .into_iter()
+        .map(|(id, subscriber)| (id, subscriber.status))
+        .collect();
+
+    for (status, action) in [
+        (SubscriptionStatus::Confirmed, ConsentAction::Confirmed),
+        (
+            SubscriptionStatus::PendingConfirmation,
+            ConsentAction::Subscribed,
+        ),
+        (
+            SubscriptionStatus::Unsubscribed,
+            ConsentAction::Unsubscribed,
+        ),
+    ] {
+        let subscriber_ids: Vec<Uuid> = inserted
+            .iter()
+            .filter(|(_, s)| *s == status)
+            .map(|(id, _)| *id)
+            .collect();
+        record_consent_events(&mut *transaction, &subscriber_ids, action, consent).await?;
+    }
+    Ok(InsertedSubscribers {
+        ids: inserted,
+        conflicts,
+    })
+}
+
+#[tracing::instrument(skip(pool, errors))]
+async fn save_import(
+    pool: &PgPool,
+    user_id: Uuid,
+    errors: &[RowError],
+) -> Result<Uuid, anyhow::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO subscriber_imports (import_id, user_id, created_at, n_imported, n_errors, error_report)
+VALUES ($1, $2, now(), 0, $3, $4)
+"#,
+        import_id,
+        user_id,
+        errors.len() as i32,
+        error_report(errors)?,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to save the outcome of an import")?;
+    Ok(import_id)
+}
+
+/// Counted in the transaction of each chunk, along with its subscribers.
+async fn record_imported_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    n_imported: usize,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriber_imports SET n_imported = n_imported + $2 WHERE import_id = $1"#,
+        import_id,
+        n_imported as i32,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to count the imported subscribers")?;
+    Ok(())
+}
+
+/// Replaces the report saved with the import, for rows that could only be
+/// skipped when inserted.
+async fn record_import_errors(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    errors: &[RowError],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriber_imports SET n_errors = $2, error_report = $3 WHERE import_id = $1"#,
+        import_id,
+        errors.len() as i32,
+        error_report(errors)?,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to save the errors of an import")?;
+    Ok(())
+}
+
+pub async fn subscriber_import_result(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let import = sqlx::query!(
+        r#"SELECT n_imported, n_errors FROM subscriber_imports WHERE import_id = $1"#,
+        import_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an import")
+    .map_err(e500)?;
+    let import = match import {
+        Some(import) => import,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    html_page(&ImportResultTemplate {
+        csrf_token: csrf_token.into_inner(),
+        import_id,
+        n_imported: import.n_imported,
+        n_errors: import.n_errors,
+    })
+}
+
+pub async fn subscriber_import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let import = sqlx::query!(
+        r#"SELECT error_report FROM subscriber_imports WHERE import_id = $1"#,
+        import_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an import")
+    .map_err(e500)?;
+    let report = match import {
+        Some(import) => import.error_report,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition::attachment(format!(
+            "import-{}-errors.csv",
+            import_id
+        )))
+        .body(report))
+}
@@ -0,0 +1,15 @@
+pub use consent::{consent_history, export_consent_events};
+pub use export::export_subscribers;
+pub use get::subscribers_list;
+pub use import::{
+    import_subscribers, import_subscribers_form, subscriber_import_report, subscriber_import_result,
+};
+pub use personal_data::{erase_subscriber, export_subscriber_data};
+pub use post::{delete_subscriber, resend_confirmation, unsubscribe_subscriber};
+
+mod consent;
+mod export;
+mod get;
+mod import;
+mod personal_data;
+mod post;
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    data_subject::erase_subject,
+    routes::personal_data_response,
+    startup::HmacSecret,
+    utils::{e500, see_other},
+};
+
+/// Addresses are used as they are: the data of someone who is not
+/// subscribed anymore can still be exported or erased.
+#[derive(serde::Deserialize)]
+pub struct EmailData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Export the personal data of a subscriber", skip_all)]
+pub async fn export_subscriber_data(
+    parameters: web::Query<EmailData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    personal_data_response(&pool, parameters.email.trim())
+        .await
+        .map_err(e500)
+}
+
+#[tracing::instrument(name = "Erase the personal data of a subscriber", skip_all)]
+pub async fn erase_subscriber(
+    form: web::Form<EmailData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    if email.is_empty() {
+        FlashMessage::error("Enter the email address to erase").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    erase_subject(&mut transaction, email, &hmac_secret.0)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the erasure")
+        .map_err(e500)?;
+    FlashMessage::success(format!("Everything stored about {} has been erased", email)).send();
+    Ok(see_other("/admin/subscribers"))
+}
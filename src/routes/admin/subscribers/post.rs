@@ -0,0 +1,148 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    consent::{record_consent_event, ConsentAction, ConsentContext},
+    domain::SubscriptionStatus,
+    email_outbox::{enqueue_email, OutboxEmail},
+    routes::{update_subscription_status, StatusUpdateError},
+    startup::PrivacyPolicyVersion,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    subscriber_id: Uuid,
+}
+
+#[tracing::instrument(name = "Resend a confirmation email", skip_all, fields(subscriber_id = %form.subscriber_id))]
+pub async fn resend_confirmation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 AND status = $2 FOR UPDATE"#,
+        form.subscriber_id,
+        SubscriptionStatus::PendingConfirmation.as_str(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve a pending subscriber")
+    .map_err(e500)?;
+    if subscriber.is_none() {
+        FlashMessage::error("Unknown subscriber, or already confirmed").send();
+        return Ok(see_other("/admin/subscribers"));
+    }
+
+    // The new link is generated when the email is sent.
+    enqueue_email(
+        &mut transaction,
+        form.subscriber_id,
+        OutboxEmail::Confirmation,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to queue a confirmation email")
+        .map_err(e500)?;
+    FlashMessage::success("The confirmation email has been sent again").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber on their behalf", skip_all, fields(subscriber_id = %form.subscriber_id))]
+pub async fn unsubscribe_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    privacy_policy_version: web::Data<PrivacyPolicyVersion>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    match update_subscription_status(
+        &mut transaction,
+        form.subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(StatusUpdateError::UnknownSubscriber) => {
+            FlashMessage::error("Unknown subscriber").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    // The IP and user agent are the admin's: the source tells them apart.
+    let consent = ConsentContext::new(&request, "admin", &privacy_policy_version.0);
+    record_consent_event(
+        &mut transaction,
+        form.subscriber_id,
+        ConsentAction::Unsubscribed,
+        &consent,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscription")
+        .map_err(e500)?;
+
+    FlashMessage::success("The subscriber has been unsubscribed").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip_all, fields(subscriber_id = %form.subscriber_id))]
+pub async fn delete_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        form.subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the confirmation tokens of a subscriber")
+    .map_err(e500)?;
+    // Pending deliveries are skipped by the workers once the subscriber is gone.
+    let n_deleted_rows = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1"#,
+        form.subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete a subscriber")
+    .map_err(e500)?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to delete a subscriber")
+        .map_err(e500)?;
+
+    if n_deleted_rows == 0 {
+        FlashMessage::error("Unknown subscriber").send();
+    } else {
+        FlashMessage::success("The subscriber has been deleted").send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,5 @@
+pub use error::ApiError;
+pub use newsletter_issues::{get_newsletter_issue, publish_newsletter_issue};
+
+mod error;
+mod newsletter_issues;
@@ -0,0 +1,54 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{ApiTokenScope, ApiTokenScopes},
+    issue_delivery_worker::DeliverIssue,
+    jobs::Job,
+    routes::ApiError,
+};
+
+#[tracing::instrument(name = "Get newsletter issue status", skip(pool, scopes))]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    scopes: web::ReqData<ApiTokenScopes>,
+) -> Result<HttpResponse, ApiError> {
+    if !scopes.contains(ApiTokenScope::ReadIssues) {
+        return Err(ApiError::MissingScope(ApiTokenScope::ReadIssues));
+    }
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+
+    let issue = sqlx::query!(
+        r#"
+SELECT
+    title,
+    status,
+    published_at,
+    (
+        SELECT COUNT(*)
+        FROM background_jobs
+        WHERE kind = $2 AND payload ->> 'newsletter_issue_id' = $3
+    ) as "pending_deliveries!"
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id,
+        DeliverIssue::KIND,
+        newsletter_issue_id.to_string(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue")?
+    .ok_or(ApiError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": newsletter_issue_id,
+        "title": issue.title,
+        "published_at": issue.published_at.to_rfc3339(),
+        "pending_deliveries": issue.pending_deliveries,
+        "status": issue.status,
+    })))
+}
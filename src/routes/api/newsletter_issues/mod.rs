@@ -0,0 +1,5 @@
+pub use get::get_newsletter_issue;
+pub use post::publish_newsletter_issue;
+
+mod get;
+mod post;
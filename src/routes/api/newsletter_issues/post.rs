@@ -0,0 +1,91 @@
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{ApiTokenScope, ApiTokenScopes, UserId},
+    email_html::process_issue_html,
+    email_template::EmailTemplates,
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    routes::{enqueue_delivery_tasks, insert_newsletter_issue, ApiError},
+};
+
+#[derive(serde::Deserialize, std::fmt::Debug)]
+pub struct BodyData {
+    title: String,
+    content_text: String,
+    content_html: String,
+    /// Whether the issue is listed in the public archive.
+    #[serde(default)]
+    is_public: bool,
+}
+
+#[tracing::instrument(
+name = "Publish a newsletter issue through the API",
+skip_all,
+fields(user_id = % & * user_id)
+)]
+pub async fn publish_newsletter_issue(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    scopes: web::ReqData<ApiTokenScopes>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    if !scopes.contains(ApiTokenScope::PublishIssues) {
+        return Err(ApiError::MissingScope(ApiTokenScope::PublishIssues));
+    }
+    let BodyData {
+        title,
+        content_text,
+        content_html,
+        is_public,
+    } = body.0;
+    let user_id = user_id.into_inner();
+    let idempotency_key: IdempotencyKey = request
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| ApiError::BadRequest("Missing `Idempotency-Key` header".into()))?
+        .to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+    let processed_html =
+        process_issue_html(&content_html).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    EmailTemplates::parse(&title, &processed_html.html, &content_text)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id).await? {
+        NextAction::StartProcessing(t) => *t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content_text,
+        &processed_html.html,
+        None,
+        is_public,
+        *user_id,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    let status_url = format!("/api/v1/newsletter_issues/{}", issue_id);
+    let response = HttpResponse::Accepted()
+        .insert_header((LOCATION, status_url.as_str()))
+        .json(serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "status_url": status_url,
+            "warnings": processed_html.warnings,
+        }));
+    let response = save_response(transaction, &idempotency_key, *user_id, response).await?;
+
+    Ok(response)
+}
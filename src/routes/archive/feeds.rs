@@ -0,0 +1,128 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use super::pages::ARCHIVE_READER;
+use crate::{email_template::EmailTemplate, startup::ApplicationBaseUrl, utils::e500};
+
+/// Feed readers only care about recent issues: the archive has the rest.
+const FEED_LENGTH: i64 = 20;
+
+struct FeedEntry {
+    id: String,
+    title: String,
+    url: String,
+    content: String,
+    /// RFC 3339 for Atom, RFC 2822 for RSS.
+    published_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "archive/atom.xml")]
+struct AtomFeedTemplate {
+    base_url: String,
+    updated_at: String,
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Template)]
+#[template(path = "archive/rss.xml")]
+struct RssFeedTemplate {
+    base_url: String,
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Clone, Copy)]
+enum DateFormat {
+    Rfc3339,
+    Rfc2822,
+}
+
+async fn feed_entries(
+    pool: &PgPool,
+    base_url: &str,
+    date_format: DateFormat,
+) -> Result<Vec<FeedEntry>, anyhow::Error> {
+    let issues = sqlx::query!(
+        r#"
+SELECT
+    newsletter_issue_id,
+    title,
+    slug,
+    html_content,
+    published_at
+FROM newsletter_issues
+WHERE is_public
+ORDER BY published_at DESC
+LIMIT $1
+"#,
+        FEED_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the public newsletter issues")?;
+
+    issues
+        .into_iter()
+        .map(|issue| {
+            let content = EmailTemplate::parse(&issue.html_content)
+                .and_then(|t| t.render_html(&ARCHIVE_READER))
+                .context("Failed to render an archived newsletter issue")?;
+            Ok(FeedEntry {
+                id: format!("urn:uuid:{}", issue.newsletter_issue_id),
+                title: issue.title,
+                url: format!("{}/archive/{}", base_url, issue.slug),
+                content,
+                published_at: match date_format {
+                    DateFormat::Rfc3339 => issue.published_at.to_rfc3339(),
+                    DateFormat::Rfc2822 => issue.published_at.to_rfc2822(),
+                },
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Serve the Atom feed of the archive", skip_all)]
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = feed_entries(&pool, &base_url.0, DateFormat::Rfc3339)
+        .await
+        .map_err(e500)?;
+    // Issues are never edited: the feed was last updated by the latest one.
+    let updated_at = entries
+        .first()
+        .map(|e| e.published_at.clone())
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+    let body = AtomFeedTemplate {
+        base_url: base_url.0.clone(),
+        updated_at,
+        entries,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(body))
+}
+
+#[tracing::instrument(name = "Serve the RSS feed of the archive", skip_all)]
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = feed_entries(&pool, &base_url.0, DateFormat::Rfc2822)
+        .await
+        .map_err(e500)?;
+    let body = RssFeedTemplate {
+        base_url: base_url.0.clone(),
+        entries,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(body))
+}
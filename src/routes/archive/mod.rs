@@ -0,0 +1,5 @@
+pub use feeds::*;
+pub use pages::*;
+
+mod feeds;
+mod pages;
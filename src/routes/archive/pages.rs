@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    email_template::{EmailTemplate, MergeFields},
+    issue_search::{search_issues, IssuePage, SearchParameters, Visibility},
+    utils::{e500, html_page},
+};
+
+/// Issues are written for subscribers: the archive fills in their placeholders
+/// for an anonymous reader. There is nothing for them to unsubscribe from: their
+/// unsubscribe links lead nowhere.
+pub(super) const ARCHIVE_READER: MergeFields = MergeFields {
+    name: "reader",
+    email: "",
+    unsubscribe_url: Some(""),
+};
+
+#[derive(Template)]
+#[template(path = "archive/index.html")]
+struct ArchiveTemplate {
+    query: String,
+    results: IssuePage,
+}
+
+#[derive(Template)]
+#[template(path = "archive/issue.html")]
+struct ArchivedIssueTemplate {
+    title: String,
+    published_on: String,
+    content: String,
+}
+
+#[tracing::instrument(name = "Show the newsletter archive", skip(pool))]
+pub async fn archive(
+    parameters: web::Query<SearchParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let results = search_issues(&pool, &parameters, Visibility::PublicOnly)
+        .await
+        .context("Failed to search the public newsletter issues")
+        .map_err(e500)?;
+
+    html_page(&ArchiveTemplate {
+        query: parameters.0.q,
+        results,
+    })
+}
+
+#[tracing::instrument(name = "Show an archived newsletter issue", skip(pool))]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = sqlx::query!(
+        r#"
+SELECT title, html_content, published_at
+FROM newsletter_issues
+WHERE slug = $1 AND is_public
+"#,
+        slug.as_str()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a public newsletter issue")
+    .map_err(e500)?;
+    let issue = match issue {
+        Some(issue) => issue,
+        // Private issues must not be told apart from missing ones.
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let content = EmailTemplate::parse(&issue.html_content)
+        .and_then(|t| t.render_html(&ARCHIVE_READER))
+        .context("Failed to render an archived newsletter issue")
+        .map_err(e500)?;
+    html_page(&ArchivedIssueTemplate {
+        title: issue.title,
+        published_on: issue.published_at.format("%B %-d, %Y").to_string(),
+        content,
+    })
+}
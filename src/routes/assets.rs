@@ -0,0 +1,9 @@
+use actix_web::HttpResponse;
+
+// The stylesheet is embedded in the binary: there is no need to ship
+// a static directory alongside it.
+pub async fn stylesheet() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(include_str!("../../static/style.css"))
+}
@@ -0,0 +1,14 @@
+use actix_web::{web, HttpResponse};
+
+/// Collect Content-Security-Policy violation reports sent by browsers.
+///
+/// Reports are only logged: they are untrusted input and may be sent by anyone.
+pub async fn csp_report(body: web::Bytes) -> HttpResponse {
+    match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(report) => {
+            tracing::warn!(csp_report = %report, "Content-Security-Policy violation reported")
+        }
+        Err(e) => tracing::debug!(error.message = %e, "Malformed CSP report"),
+    }
+    HttpResponse::NoContent().finish()
+}
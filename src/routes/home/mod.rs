@@ -1,5 +1,11 @@
-use actix_web::HttpResponse;
+use askama::Template;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok().body(include_str!("home.html"))
+use crate::utils::html_page;
+
+#[derive(Template)]
+#[template(path = "home.html")]
+struct HomeTemplate;
+
+pub async fn home() -> Result<actix_web::HttpResponse, actix_web::Error> {
+    html_page(&HomeTemplate)
 }
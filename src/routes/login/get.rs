@@ -1,45 +1,17 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut messages_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(messages_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+use crate::utils::html_page;
 
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Login</title>
-</head>
-<body>
-    {}
-    <form action="/login" method="post">
-    <label>Username
-    <input
-        type="text"
-        placeholder="Enter Username"
-        name="username"
-    >
-    </label>
-    <label>Password
-        <input
-            type="password"
-            placeholder="Enter Password"
-            name="password"
-        >
-    </label>
-    <button type="submit">Login</button>
-    </form>
-</body>
-</html>
-"#,
-            messages_html
-        ))
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate {
+    flash_messages: IncomingFlashMessages,
+}
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    html_page(&LoginTemplate { flash_messages })
 }
@@ -1,14 +1,21 @@
-use actix_web::{error::InternalError, HttpResponse};
-use actix_web::{http::header::LOCATION, web};
+use actix_web::{error::InternalError, HttpRequest, HttpResponse};
+use actix_web::{
+    http::header::{LOCATION, USER_AGENT},
+    web,
+};
 use actix_web_flash_messages::FlashMessage;
 use reqwest::StatusCode;
 use secrecy::Secret;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    authentication::{
+        create_user_session, validate_credentials, AuthError, Credentials, CsrfToken,
+    },
+    configuration::SessionSettings,
     routes::error_chain_fmt,
     session_state::TypedSession,
+    utils::client_ip,
 };
 
 #[derive(serde::Deserialize)]
@@ -18,27 +25,45 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-skip(form, pool, session),
+skip(form, pool, session, request, settings),
 fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    request: HttpRequest,
+    settings: web::Data<SessionSettings>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
 
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let ip_address = client_ip(&request);
+            let user_agent = request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok());
+            let session_id =
+                create_user_session(&pool, user_id, ip_address.as_deref(), user_agent, &settings)
+                    .await
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+
             session.renew();
             session
                 .insert_user_id(user_id)
                 .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            session
+                .insert_session_id(session_id)
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            session
+                .insert_csrf_token(CsrfToken::generate().as_ref().to_owned())
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
 
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
@@ -1,13 +1,28 @@
 pub use admin::*;
+pub use api::*;
+pub use archive::*;
+pub use assets::*;
+pub use csp_report::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use privacy::*;
+pub use subscription_pages::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::unsubscribe_url;
 
 mod admin;
+mod api;
+mod archive;
+mod assets;
+mod csp_report;
 pub mod health_check;
 mod home;
 mod login;
+mod privacy;
+mod subscription_pages;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod subscriptions_resend;
+pub mod subscriptions_unsubscribe;
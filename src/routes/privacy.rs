@@ -0,0 +1,173 @@
+//! Requests from people for the data we store about their email address.
+//!
+//! Anyone can ask, but the data is only handed over, or erased, through a
+//! link sent to the address itself.
+use actix_web::{http::header::ContentDisposition, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    data_subject::{collect_subject_data, data_request_email, erase_subject, request_subject_data},
+    domain::SubscriberEmail,
+    routes::error_chain_fmt,
+    startup::HmacSecret,
+    utils::{html_page, see_other},
+};
+
+// The same message is shown whether or not we know the address,
+// not to disclose who is subscribed.
+const REQUEST_SENT_MESSAGE: &str = "If we store any data about this address, \
+    we have sent it a link to download or erase it.";
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestParameters {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "privacy.html")]
+struct PrivacyTemplate {
+    flash_messages: IncomingFlashMessages,
+}
+
+#[derive(Template)]
+#[template(path = "data_request.html")]
+struct DataRequestTemplate {
+    email: String,
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "data_erased.html")]
+struct DataErasedTemplate;
+
+pub async fn privacy_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    html_page(&PrivacyTemplate { flash_messages })
+}
+
+#[tracing::instrument(name = "Request personal data", skip_all)]
+pub async fn request_personal_data(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/privacy"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(DataRequestError::UnexpectedError)?;
+    request_subject_data(&mut transaction, email.as_ref())
+        .await
+        .map_err(DataRequestError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to queue a data request link")
+        .map_err(DataRequestError::UnexpectedError)?;
+    FlashMessage::info(REQUEST_SENT_MESSAGE).send();
+    Ok(see_other("/privacy"))
+}
+
+// Link scanners follow links on their own: erasing the data takes an
+// explicit confirmation from the subscriber.
+pub async fn data_request(
+    parameters: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = validate_request(&pool, &parameters.token).await?;
+    html_page(&DataRequestTemplate {
+        email,
+        token: parameters.0.token,
+    })
+}
+
+#[tracing::instrument(name = "Export personal data", skip_all)]
+pub async fn export_personal_data(
+    parameters: web::Query<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = validate_request(&pool, &parameters.token).await?;
+    Ok(personal_data_response(&pool, &email)
+        .await
+        .map_err(DataRequestError::UnexpectedError)?)
+}
+
+/// A JSON file with everything stored about `email`.
+pub async fn personal_data_response(
+    pool: &PgPool,
+    email: &str,
+) -> Result<HttpResponse, anyhow::Error> {
+    let data = collect_subject_data(pool, email).await?;
+    let body = serde_json::to_vec_pretty(&data).context("Failed to serialize personal data")?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(ContentDisposition::attachment("personal-data.json"))
+        .body(body))
+}
+
+#[tracing::instrument(name = "Erase personal data", skip_all)]
+pub async fn erase_personal_data(
+    form: web::Form<DataRequestParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = validate_request(&pool, &form.token).await?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(DataRequestError::UnexpectedError)?;
+    erase_subject(&mut transaction, &email, &hmac_secret.0)
+        .await
+        .map_err(DataRequestError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the erasure")
+        .map_err(DataRequestError::UnexpectedError)?;
+    html_page(&DataErasedTemplate)
+}
+
+async fn validate_request(pool: &PgPool, token: &str) -> Result<String, DataRequestError> {
+    data_request_email(pool, token)
+        .await?
+        .ok_or(DataRequestError::UnknownToken)
+}
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("The link is not valid, or has expired")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl actix_web::error::ResponseError for DataRequestError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            DataRequestError::UnknownToken => reqwest::StatusCode::UNAUTHORIZED,
+            DataRequestError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
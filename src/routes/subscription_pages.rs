@@ -0,0 +1,60 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    HttpResponse,
+};
+use anyhow::Context;
+use askama::Template;
+
+use crate::{configuration::SubscriptionPagesSettings, utils::see_other};
+
+/// The pages people land on while subscribing.
+#[derive(Debug, Clone, Copy)]
+pub enum SubscriptionPage {
+    CheckInbox,
+    Confirmed,
+    LinkExpired,
+    InvalidLink,
+}
+
+#[derive(Template)]
+#[template(path = "subscription_check_inbox.html")]
+struct CheckInboxTemplate;
+
+#[derive(Template)]
+#[template(path = "subscription_confirmed.html")]
+struct ConfirmedTemplate;
+
+#[derive(Template)]
+#[template(path = "confirmation_expired.html")]
+struct LinkExpiredTemplate;
+
+#[derive(Template)]
+#[template(path = "confirmation_invalid.html")]
+struct InvalidLinkTemplate;
+
+/// Redirect to the page configured for `page`, or render ours with `status`.
+pub fn subscription_page(
+    pages: &SubscriptionPagesSettings,
+    page: SubscriptionPage,
+    status: StatusCode,
+) -> Result<HttpResponse, anyhow::Error> {
+    let url = match page {
+        SubscriptionPage::CheckInbox => &pages.check_inbox_url,
+        SubscriptionPage::Confirmed => &pages.confirmed_url,
+        SubscriptionPage::LinkExpired => &pages.link_expired_url,
+        SubscriptionPage::InvalidLink => &pages.invalid_link_url,
+    };
+    if let Some(url) = url {
+        return Ok(see_other(url));
+    }
+    let body = match page {
+        SubscriptionPage::CheckInbox => CheckInboxTemplate.render(),
+        SubscriptionPage::Confirmed => ConfirmedTemplate.render(),
+        SubscriptionPage::LinkExpired => LinkExpiredTemplate.render(),
+        SubscriptionPage::InvalidLink => InvalidLinkTemplate.render(),
+    }
+    .with_context(|| format!("Failed to render the {:?} page", page))?;
+    Ok(HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(body))
+}
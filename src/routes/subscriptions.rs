@@ -1,22 +1,47 @@
 //! src/routes/subscriptions.rs
 
-use actix_web::{web, HttpResponse};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
 use sqlx::{types::Uuid, PgPool, Postgres, Transaction};
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    configuration::SubscriptionPagesSettings,
+    consent::{record_consent_event, ConsentAction, ConsentContext},
+    domain::{
+        IllegalTransition, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus,
+    },
     email_client::EmailClient,
-    routes::error_chain_fmt,
-    startup::ApplicationBaseUrl,
+    email_outbox::{enqueue_email, OutboxEmail, QueuedEmail},
+    email_template::{EmailTemplates, MergeFields},
+    jobs::Job,
+    routes::{error_chain_fmt, subscription_page, unsubscribe_url, SubscriptionPage},
+    startup::PrivacyPolicyVersion,
 };
 
+/// The source recorded for subscriptions made without one.
+const DEFAULT_SOURCE: &str = "subscription_form";
+
+/// How long confirmation links remain valid.
+pub const CONFIRMATION_TOKEN_TTL_HOURS: i64 = 72;
+
+/// Confirmation emails sent to an address over `RATE_LIMIT_WINDOW_MINUTES`,
+/// including the one sent when subscribing.
+const MAX_CONFIRMATION_EMAILS: i64 = 3;
+const RATE_LIMIT_WINDOW_MINUTES: i64 = 60;
+/// Confirmed subscribers are told they are already subscribed at most once
+/// over this window.
+const ALREADY_SUBSCRIBED_EMAIL_WINDOW_MINUTES: i64 = 60;
+
 #[derive(Debug, serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Which form the subscription was made with, recorded as proof of consent.
+    #[serde(default)]
+    source: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -30,7 +55,7 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -38,9 +63,22 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// The link emailed to subscribers to confirm their subscription.
+pub fn confirmation_url(base_url: &str, token: &str) -> String {
+    format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, token
+    )
+}
+
+/// Only the digest of confirmation tokens is stored.
+pub fn hash_subscription_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
 #[tracing::instrument(
 name = "Adding a new subscriber",
-skip(form, pool),
+skip(form, pool, request, pages),
 fields(
 subscriber_email = % form.email,
 subscriber_name = % form.name,
@@ -49,9 +87,14 @@ subscriber_name = % form.name,
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
+    privacy_policy_version: web::Data<PrivacyPolicyVersion>,
+    pages: web::Data<SubscriptionPagesSettings>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let source = match form.source.as_deref() {
+        Some(source) if !source.trim().is_empty() => source.trim().to_owned(),
+        _ => DEFAULT_SOURCE.to_owned(),
+    };
     // `web::Form` is a wrapper around `FormData`
     // `form.0` gives us access to the underlying `FormData`
     let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
@@ -61,48 +104,222 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
-        .await
-        .context("Failed to insert new subscriber in the database.")?;
+    // Every case gets the same response, not to disclose who is subscribed.
+    let subscriber_id = match find_or_insert_subscriber(&new_subscriber, &mut transaction).await? {
+        Subscriber::New(subscriber_id) => subscriber_id,
+        Subscriber::Existing(existing) if existing.status == SubscriptionStatus::Confirmed => {
+            if claim_already_subscribed_email(&mut transaction, existing.id).await? {
+                enqueue_email(
+                    &mut transaction,
+                    existing.id,
+                    OutboxEmail::AlreadySubscribed,
+                )
+                .await
+                .context("Failed to queue an already subscribed email.")?;
+            } else {
+                tracing::warn!("Too many already subscribed emails requested");
+            }
+            transaction
+                .commit()
+                .await
+                .context("Failed to queue an already subscribed email.")?;
+            return Ok(subscription_page(
+                &pages,
+                SubscriptionPage::CheckInbox,
+                StatusCode::OK,
+            )?);
+        }
+        // Subscribing again is no way around the limit on resent emails.
+        Subscriber::Existing(existing)
+            if too_many_confirmation_emails(&mut transaction, existing.id).await? =>
+        {
+            tracing::warn!("Too many confirmation emails requested");
+            return Ok(subscription_page(
+                &pages,
+                SubscriptionPage::CheckInbox,
+                StatusCode::OK,
+            )?);
+        }
+        // Pending subscribers get a new confirmation email, unsubscribed
+        // ones must confirm their subscription again.
+        Subscriber::Existing(existing) => {
+            ask_for_confirmation_again(&new_subscriber, &existing, &mut transaction)
+                .await
+                .context("Failed to update an existing subscriber.")?;
+            existing.id
+        }
+    };
 
-    let token = generate_subscription_token();
-    store_token(&token, &subscriber_id, &mut transaction)
+    let consent = ConsentContext::new(&request, &source, &privacy_policy_version.0);
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentAction::Subscribed,
+        &consent,
+    )
+    .await?;
+    enqueue_email(&mut transaction, subscriber_id, OutboxEmail::Confirmation)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to queue a confirmation email.")?;
 
     transaction
         .commit()
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to commit a new subscription.")?;
 
-    send_confirmation_email(&email_client, new_subscriber, &base_url.0, &token)
-        .await
-        .context("Failed to send a confirmation email.")?;
+    Ok(subscription_page(
+        &pages,
+        SubscriptionPage::CheckInbox,
+        StatusCode::OK,
+    )?)
+}
 
-    Ok(HttpResponse::Ok().finish())
+/// Whether `subscriber_id` was sent as many confirmation emails as they
+/// can get for now. Call with the subscriber locked, for concurrent requests
+/// to see the emails queued by one another.
+pub async fn too_many_confirmation_emails(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    // Queued emails have not created their token yet.
+    let n_recent_emails = sqlx::query!(
+        r#"
+SELECT
+    (SELECT count(*) FROM subscription_tokens WHERE subscriber_id = $1 AND created_at > $2)
+    + (
+        SELECT count(*) FROM background_jobs
+        WHERE kind = $3 AND payload ->> 'subscriber_id' = $4 AND payload ->> 'email' = $5
+    )
+    AS "count!"
+"#,
+        subscriber_id,
+        Utc::now() - Duration::minutes(RATE_LIMIT_WINDOW_MINUTES),
+        QueuedEmail::KIND,
+        subscriber_id.to_string(),
+        OutboxEmail::Confirmation.as_str(),
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Failed to count the recent confirmation emails")?
+    .count;
+    Ok(n_recent_emails >= MAX_CONFIRMATION_EMAILS)
+}
+
+/// Whether an already subscribed email can be sent to `subscriber_id`, in
+/// which case it is recorded as sent.
+async fn claim_already_subscribed_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let now = Utc::now();
+    let n_updated_rows = sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET already_subscribed_email_sent_at = $2
+    WHERE id = $1
+        AND (already_subscribed_email_sent_at IS NULL OR already_subscribed_email_sent_at <= $3)
+    "#,
+        subscriber_id,
+        now,
+        now - Duration::minutes(ALREADY_SUBSCRIBED_EMAIL_WINDOW_MINUTES),
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to record an already subscribed email")?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
 }
 
 #[tracing::instrument(name = "Store subscription token in the database", skip(transaction))]
-async fn store_token(
+pub async fn store_token(
     token: &str,
     subscriber_id: &Uuid,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<(), StoreTokenError> {
+    let now = Utc::now();
     sqlx::query!(
         r#"
-    INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-    VALUES ($1, $2)
+    INSERT INTO subscription_tokens (token_hash, subscriber_id, created_at, expires_at)
+    VALUES ($1, $2, $3, $4)
     "#,
-        &token,
+        hash_subscription_token(token),
         &subscriber_id,
+        now,
+        now + Duration::hours(CONFIRMATION_TOKEN_TTL_HOURS),
     )
     .execute(transaction)
     .await
-    .map_err(|e| StoreTokenError(e))?;
+    .map_err(StoreTokenError)?;
 
     Ok(())
 }
 
+struct ExistingSubscriber {
+    id: Uuid,
+    status: SubscriptionStatus,
+}
+
+enum Subscriber {
+    New(Uuid),
+    Existing(ExistingSubscriber),
+}
+
+async fn find_or_insert_subscriber(
+    new_subscriber: &NewSubscriber,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Subscriber, anyhow::Error> {
+    if let Some(existing) = find_subscriber(new_subscriber, transaction)
+        .await
+        .context("Failed to look for an existing subscriber.")?
+    {
+        return Ok(Subscriber::Existing(existing));
+    }
+    if let Some(id) = insert_subscriber(new_subscriber, transaction)
+        .await
+        .context("Failed to insert new subscriber in the database.")?
+    {
+        return Ok(Subscriber::New(id));
+    }
+    // Someone subscribed with the same email in the meantime.
+    let existing = find_subscriber(new_subscriber, transaction)
+        .await
+        .context("Failed to look for an existing subscriber.")?
+        .context("The conflicting subscriber could not be found.")?;
+    Ok(Subscriber::Existing(existing))
+}
+
+/// Emails are matched regardless of their case. The row is locked until the
+/// end of the transaction, for concurrent requests to see its new state.
+#[tracing::instrument(
+    name = "Look for an existing subscriber",
+    skip(new_subscriber, transaction)
+)]
+async fn find_subscriber(
+    new_subscriber: &NewSubscriber,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<ExistingSubscriber>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT id, status
+    FROM subscriptions
+    WHERE lower(email) = lower($1)
+    FOR UPDATE
+    "#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await?;
+    let existing = match row {
+        Some(r) => ExistingSubscriber {
+            id: r.id,
+            status: SubscriptionStatus::parse(&r.status).map_err(anyhow::Error::msg)?,
+        },
+        None => return Ok(None),
+    };
+    Ok(Some(existing))
+}
+
+/// Returns `None` if the email is already taken.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -110,23 +327,62 @@ async fn store_token(
 async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let id = Uuid::new_v4();
 
-    sqlx::query!(
+    let n_inserted_rows = sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (email) DO NOTHING
     "#,
         &id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        SubscriptionStatus::PendingConfirmation.as_str(),
+        generate_subscription_token(),
     )
     .execute(transaction)
-    .await?;
+    .await?
+    .rows_affected();
+
+    Ok((n_inserted_rows == 1).then_some(id))
+}
 
-    Ok(id)
+/// Back to `pending_confirmation`, with the name that was just submitted.
+#[tracing::instrument(
+    name = "Ask an existing subscriber for confirmation again",
+    skip(new_subscriber, existing, transaction),
+    fields(subscriber_id = %existing.id)
+)]
+async fn ask_for_confirmation_again(
+    new_subscriber: &NewSubscriber,
+    existing: &ExistingSubscriber,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), anyhow::Error> {
+    let status = existing
+        .status
+        .transition_to(SubscriptionStatus::PendingConfirmation)?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2, name = $3 WHERE id = $1"#,
+        existing.id,
+        status.as_str(),
+        new_subscriber.name.as_ref(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Pending subscribers keep their place in the reminder and purge schedule.
+    if existing.status != SubscriptionStatus::PendingConfirmation {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET pending_since = $2, reminder_sent_at = NULL WHERE id = $1"#,
+            existing.id,
+            Utc::now(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -138,23 +394,121 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, token
-    );
+) -> Result<(), anyhow::Error> {
+    let confirmation_link = confirmation_url(base_url, token);
     let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "Welcome to our newsletter, {{{{ name }}}}!\nVisit {} to confirm your subscription.\n\
+        The link expires in {} hours.",
+        confirmation_link, CONFIRMATION_TOKEN_TTL_HOURS
     );
     let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
+        "Welcome to our newsletter, {{{{ name }}}}!<br />\
+        Click <a href=\"{}\">here</a> to confirm your subscription.<br />\
+        The link expires in {} hours.",
+        confirmation_link, CONFIRMATION_TOKEN_TTL_HOURS
     );
+    let email =
+        EmailTemplates::parse("Welcome!", &html_body, &plain_body)?.render(&MergeFields {
+            name: new_subscriber.name.as_ref(),
+            email: new_subscriber.email.as_ref(),
+            // Subscribers can only unsubscribe once they are confirmed.
+            unsubscribe_url: None,
+        })?;
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .send_email(
+            &new_subscriber.email,
+            &email.subject,
+            &email.html_body,
+            &email.text_body,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Sent instead of a confirmation email to people who are already subscribed.
+#[tracing::instrument(
+    name = "Tell a subscriber they are already subscribed",
+    skip(email_client, email, name, base_url, unsubscribe_token)
+)]
+pub async fn send_already_subscribed_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    name: &str,
+    base_url: &str,
+    unsubscribe_token: &str,
+) -> Result<(), anyhow::Error> {
+    let plain_body = "Hello {{ name }}!\n\
+        Someone, hopefully you, tried to subscribe to our newsletter with this address: \
+        you are already subscribed, there is nothing else to do.\n\
+        Visit {{ unsubscribe_url }} if you want to stop receiving it.";
+    let html_body = "Hello {{ name }}!<br />\
+        Someone, hopefully you, tried to subscribe to our newsletter with this address: \
+        you are already subscribed, there is nothing else to do.<br />\
+        Click <a href=\"{{ unsubscribe_url }}\">here</a> if you want to stop receiving it.";
+    let unsubscribe_url = unsubscribe_url(base_url, unsubscribe_token);
+    let message = EmailTemplates::parse("You are already subscribed", html_body, plain_body)?
+        .render(&MergeFields {
+            name,
+            email: email.as_ref(),
+            unsubscribe_url: Some(&unsubscribe_url),
+        })?;
+    email_client
+        .send_email(
+            email,
+            &message.subject,
+            &message.html_body,
+            &message.text_body,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Move a subscriber to `next`, if its current status allows it.
+/// Returns the previous status.
+#[tracing::instrument(name = "Update the status of a subscriber", skip(transaction))]
+pub async fn update_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+) -> Result<SubscriptionStatus, StatusUpdateError> {
+    // Locked, for concurrent updates to start from the new status.
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the status of a subscriber")?;
+    let current = match row {
+        Some(r) => SubscriptionStatus::parse(&r.status).map_err(anyhow::Error::msg)?,
+        None => return Err(StatusUpdateError::UnknownSubscriber),
+    };
+    let next = current.transition_to(next)?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        next.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the status of a subscriber")?;
+    Ok(current)
+}
+
+#[derive(thiserror::Error)]
+pub enum StatusUpdateError {
+    #[error("There is no subscriber with this id")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    IllegalTransition(#[from] IllegalTransition),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for StatusUpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
 }
 
 #[derive(thiserror::Error)]
@@ -1,9 +1,22 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::{
+    configuration::SubscriptionPagesSettings,
+    consent::{record_consent_event, ConsentAction, ConsentContext},
+    domain::{IllegalTransition, SubscriptionStatus},
+    email_outbox::{enqueue_email, OutboxEmail},
+    routes::{
+        hash_subscription_token, subscription_page, update_subscription_status, StatusUpdateError,
+        SubscriptionPage,
+    },
+    startup::{PrivacyPolicyVersion, WelcomeEmail},
+};
+
 #[derive(Debug, Deserialize)]
 pub struct SubscriberToken(String);
 
@@ -58,55 +71,187 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters))]
+enum Confirmation {
+    Confirmed,
+    /// The link was used already, to confirm the subscription.
+    AlreadyConfirmed,
+    Expired,
+}
+
+#[tracing::instrument(name = "Confirm a pending subscriber", skip_all)]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    request: HttpRequest,
+    privacy_policy_version: web::Data<PrivacyPolicyVersion>,
+    pages: web::Data<SubscriptionPagesSettings>,
+    welcome_email: web::Data<WelcomeEmail>,
 ) -> Result<HttpResponse, ConfirmSubscriptionError> {
-    let token = SubscriberToken::parse(parameters.0.subscription_token)
+    let confirmation = try_confirm(
+        parameters.0,
+        &pool,
+        &request,
+        &privacy_policy_version.0,
+        welcome_email.0.is_some(),
+    )
+    .await;
+    match confirmation {
+        Ok(Confirmation::Confirmed | Confirmation::AlreadyConfirmed) => {}
+        Ok(Confirmation::Expired) => {
+            return Ok(subscription_page(
+                &pages,
+                SubscriptionPage::LinkExpired,
+                StatusCode::GONE,
+            )?);
+        }
+        Err(
+            e @ (ConfirmSubscriptionError::UnknownToken
+            | ConfirmSubscriptionError::InvalidTokenFormat(_)
+            | ConfirmSubscriptionError::InvalidStatus(_)),
+        ) => {
+            tracing::info!(error = %e, "Invalid confirmation link");
+            return Ok(subscription_page(
+                &pages,
+                SubscriptionPage::InvalidLink,
+                e.status_code(),
+            )?);
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(subscription_page(
+        &pages,
+        SubscriptionPage::Confirmed,
+        StatusCode::OK,
+    )?)
+}
+
+async fn try_confirm(
+    parameters: Parameters,
+    pool: &PgPool,
+    request: &HttpRequest,
+    privacy_policy_version: &str,
+    send_welcome_email: bool,
+) -> Result<Confirmation, ConfirmSubscriptionError> {
+    let token = SubscriberToken::parse(parameters.subscription_token)
         .map_err(ConfirmSubscriptionError::InvalidTokenFormat)?;
 
-    let subscriber_id = get_subscriber_id_from_token(&pool, &token)
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let stored_token = consume_token(&mut transaction, &token)
         .await
         .context("Failed to retrieve subscriber for associated token")?
         .ok_or(ConfirmSubscriptionError::UnknownToken)?;
-
-    confirm_subscriber(&pool, subscriber_id)
+    if stored_token.consumed_at.is_some() {
+        return already_confirmed(&mut transaction, stored_token.subscriber_id).await;
+    }
+    if stored_token.expires_at < Utc::now() {
+        // Rolled back: the link keeps showing this page until it is purged.
+        return Ok(Confirmation::Expired);
+    }
+    let subscriber_id = stored_token.subscriber_id;
+    // Subscribers who opted out since the link was sent have to subscribe again.
+    update_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .map_err(|e| match e {
+        StatusUpdateError::IllegalTransition(e) => ConfirmSubscriptionError::InvalidStatus(e),
+        e => ConfirmSubscriptionError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to mark a subscriber as confirmed"),
+        ),
+    })?;
+    let consent = ConsentContext::new(request, "confirmation_link", privacy_policy_version);
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentAction::Confirmed,
+        &consent,
+    )
+    .await?;
+    if send_welcome_email {
+        enqueue_email(&mut transaction, subscriber_id, OutboxEmail::Welcome).await?;
+    }
+    transaction
+        .commit()
         .await
-        .context("Failed to update token to `confirmed`")?;
+        .context("Failed to commit the confirmation")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(Confirmation::Confirmed)
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(
-    pool: &PgPool,
+/// Links opened again, after confirming, show the same page as the first time.
+/// Link scanners may have opened them before the subscriber did.
+async fn already_confirmed(
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-) -> Result<(), UpdateTokenStatusError> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+) -> Result<Confirmation, ConfirmSubscriptionError> {
+    let status = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1"#,
         subscriber_id,
     )
-    .execute(pool)
+    .fetch_one(&mut *transaction)
     .await
-    .map_err(UpdateTokenStatusError)?;
+    .context("Failed to retrieve the subscriber of a used token")?
+    .status;
+    if status == SubscriptionStatus::Confirmed.as_str() {
+        Ok(Confirmation::AlreadyConfirmed)
+    } else {
+        Err(ConfirmSubscriptionError::UnknownToken)
+    }
+}
 
-    Ok(())
+pub struct StoredToken {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+/// Mark the token as consumed, and delete the other tokens of its subscriber:
+/// confirmation links can only be used once. Returns the token as it was
+/// before, consumed already or not.
+#[tracing::instrument(
+    name = "Consume a subscription token",
+    skip(subscription_token, transaction)
+)]
+pub async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &SubscriberToken,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token.as_ref(),
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    let token_hash = hash_subscription_token(subscription_token.as_ref());
+    let stored_token = sqlx::query_as!(
+        StoredToken,
+        r#"
+SELECT subscriber_id, expires_at, consumed_at
+FROM subscription_tokens
+WHERE token_hash = $1
+FOR UPDATE
+"#,
+        token_hash,
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *transaction)
     .await?;
-
-    Ok(result.map(|r| r.subscriber_id))
+    if let Some(stored_token) = &stored_token {
+        if stored_token.consumed_at.is_none() {
+            sqlx::query!(
+                r#"UPDATE subscription_tokens SET consumed_at = now() WHERE token_hash = $1"#,
+                token_hash,
+            )
+            .execute(&mut *transaction)
+            .await?;
+            sqlx::query!(
+                r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND token_hash <> $2"#,
+                stored_token.subscriber_id,
+                token_hash,
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    Ok(stored_token)
 }
 
 #[derive(thiserror::Error)]
@@ -114,6 +259,8 @@ pub enum ConfirmSubscriptionError {
     #[error("There is no subscriber associated with the provided token")]
     UnknownToken,
     #[error(transparent)]
+    InvalidStatus(IllegalTransition),
+    #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
     #[error(transparent)]
     InvalidTokenFormat(#[from] ParseTokenError),
@@ -129,6 +276,7 @@ impl actix_web::error::ResponseError for ConfirmSubscriptionError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
             ConfirmSubscriptionError::UnknownToken => reqwest::StatusCode::UNAUTHORIZED,
+            ConfirmSubscriptionError::InvalidStatus(_) => reqwest::StatusCode::CONFLICT,
             ConfirmSubscriptionError::InvalidTokenFormat(_) => reqwest::StatusCode::BAD_REQUEST,
             ConfirmSubscriptionError::UnexpectedError(_) => {
                 reqwest::StatusCode::INTERNAL_SERVER_ERROR
@@ -137,31 +285,6 @@ impl actix_web::error::ResponseError for ConfirmSubscriptionError {
     }
 }
 
-pub struct UpdateTokenStatusError(sqlx::Error);
-
-impl std::error::Error for UpdateTokenStatusError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        // The compiler transparently casts `&sqlx::Error` into a `&dyn Error`
-        Some(&self.0)
-    }
-}
-
-impl std::fmt::Display for UpdateTokenStatusError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "A database error was encountered while \
-                trying to update the status of a subscription token."
-        )
-    }
-}
-
-impl std::fmt::Debug for UpdateTokenStatusError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
@@ -0,0 +1,78 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriptionStatus},
+    email_outbox::{enqueue_email, OutboxEmail},
+    routes::too_many_confirmation_emails,
+    utils::{e400, e500, html_page},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[derive(Template)]
+#[template(path = "confirmation_resent.html")]
+struct ConfirmationResentTemplate;
+
+// The same page is shown whether or not a new email was sent,
+// not to disclose who is subscribed.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip_all,
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Locked, for concurrent requests to see the email queued by this one.
+    let subscriber = sqlx::query!(
+        r#"
+SELECT id
+FROM subscriptions
+WHERE lower(email) = lower($1) AND status = $2
+FOR UPDATE
+"#,
+        email.as_ref(),
+        SubscriptionStatus::PendingConfirmation.as_str(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve a pending subscriber")
+    .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(s) => s,
+        None => return html_page(&ConfirmationResentTemplate),
+    };
+
+    if too_many_confirmation_emails(&mut transaction, subscriber.id)
+        .await
+        .map_err(e500)?
+    {
+        tracing::warn!("Too many confirmation emails requested");
+        return html_page(&ConfirmationResentTemplate);
+    }
+
+    enqueue_email(&mut transaction, subscriber.id, OutboxEmail::Confirmation)
+        .await
+        .context("Failed to queue a confirmation email")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to queue a confirmation email")
+        .map_err(e500)?;
+    html_page(&ConfirmationResentTemplate)
+}
@@ -0,0 +1,121 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    consent::{record_consent_event, ConsentAction, ConsentContext},
+    domain::SubscriptionStatus,
+    routes::{error_chain_fmt, update_subscription_status},
+    startup::PrivacyPolicyVersion,
+    utils::html_page,
+};
+
+/// The link embedded in every issue for subscribers to opt out.
+pub fn unsubscribe_url(base_url: &str, unsubscribe_token: &str) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        base_url, unsubscribe_token
+    )
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    unsubscribe_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+struct UnsubscribeTemplate {
+    unsubscribe_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribed.html")]
+struct UnsubscribedTemplate;
+
+// Email clients and link scanners follow links on their own:
+// unsubscribing takes an explicit confirmation from the subscriber.
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+) -> Result<HttpResponse, actix_web::Error> {
+    html_page(&UnsubscribeTemplate {
+        unsubscribe_token: parameters.0.unsubscribe_token,
+    })
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, pool, request, privacy_policy_version)
+)]
+pub async fn unsubscribe(
+    form: web::Form<Parameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    privacy_policy_version: web::Data<PrivacyPolicyVersion>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(UnsubscribeError::UnexpectedError)?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE unsubscribe_token = $1"#,
+        form.0.unsubscribe_token,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve a subscriber")
+    .map_err(UnsubscribeError::UnexpectedError)?;
+
+    let subscriber_id = match subscriber {
+        Some(s) => s.id,
+        None => return Err(UnsubscribeError::UnknownToken.into()),
+    };
+    update_subscription_status(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    .context("Failed to mark a subscriber as unsubscribed")
+    .map_err(UnsubscribeError::UnexpectedError)?;
+    let consent = ConsentContext::new(&request, "unsubscribe_link", &privacy_policy_version.0);
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentAction::Unsubscribed,
+        &consent,
+    )
+    .await
+    .map_err(UnsubscribeError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscription")
+        .map_err(UnsubscribeError::UnexpectedError)?;
+    html_page(&UnsubscribedTemplate)
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("There is no subscriber associated with the provided token")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl actix_web::error::ResponseError for UnsubscribeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            UnsubscribeError::UnknownToken => reqwest::StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY,
+    REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+
+use crate::configuration::SecurityHeadersSettings;
+use crate::utils::e500;
+
+/// Where browsers send Content-Security-Policy violation reports.
+pub const CSP_REPORT_PATH: &str = "/csp_report";
+
+/// Added to the extensions of responses embedding HTML with inline styles,
+/// such as the preview of an email, for their policy to allow them.
+pub struct AllowInlineStyles;
+
+/// Add a secure default set of headers to every response.
+///
+/// Headers already set by a handler are left untouched.
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req
+        .app_data::<web::Data<SecurityHeadersSettings>>()
+        .ok_or_else(|| e500("The security headers settings are not registered as app data"))?
+        .clone();
+
+    let mut response = next.call(req).await?;
+    let inline_styles = response
+        .response()
+        .extensions()
+        .get::<AllowInlineStyles>()
+        .is_some();
+    let headers = response.headers_mut();
+    for (name, value) in security_headers(&settings, inline_styles) {
+        if !headers.contains_key(&name) {
+            let value = HeaderValue::try_from(value).map_err(e500)?;
+            headers.insert(name, value);
+        }
+    }
+    Ok(response)
+}
+
+fn security_headers(
+    settings: &SecurityHeadersSettings,
+    inline_styles: bool,
+) -> Vec<(HeaderName, String)> {
+    let csp_header = if settings.csp_report_only {
+        CONTENT_SECURITY_POLICY_REPORT_ONLY
+    } else {
+        CONTENT_SECURITY_POLICY
+    };
+    let mut directives: Vec<&str> = settings
+        .content_security_policy
+        .split(';')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .collect();
+    if inline_styles {
+        // Browsers only apply the first of duplicate directives.
+        directives.retain(|d| !d.starts_with("style-src"));
+        directives.push("style-src 'self' 'unsafe-inline'");
+    }
+    let csp = format!("{}; report-uri {}", directives.join("; "), CSP_REPORT_PATH);
+
+    let mut headers = vec![
+        (csp_header, csp),
+        (X_FRAME_OPTIONS, settings.frame_options.clone()),
+        (REFERRER_POLICY, settings.referrer_policy.clone()),
+        (X_CONTENT_TYPE_OPTIONS, "nosniff".into()),
+    ];
+    if let Some(max_age) = settings.hsts_max_age_seconds {
+        headers.push((
+            STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}; includeSubDomains", max_age),
+        ));
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{
+        CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY, STRICT_TRANSPORT_SECURITY,
+    };
+
+    use super::security_headers;
+    use crate::configuration::SecurityHeadersSettings;
+
+    fn settings() -> SecurityHeadersSettings {
+        SecurityHeadersSettings {
+            content_security_policy: "default-src 'self';".into(),
+            csp_report_only: false,
+            frame_options: "DENY".into(),
+            referrer_policy: "no-referrer".into(),
+            hsts_max_age_seconds: None,
+        }
+    }
+
+    #[test]
+    fn the_policy_is_enforced_and_reports_violations_by_default() {
+        let headers = security_headers(&settings(), false);
+        let (_, csp) = headers
+            .iter()
+            .find(|(name, _)| name == CONTENT_SECURITY_POLICY)
+            .unwrap();
+        assert_eq!(csp, "default-src 'self'; report-uri /csp_report");
+        assert!(!headers
+            .iter()
+            .any(|(name, _)| name == CONTENT_SECURITY_POLICY_REPORT_ONLY));
+    }
+
+    #[test]
+    fn the_policy_is_only_reported_in_report_only_mode() {
+        let settings = SecurityHeadersSettings {
+            csp_report_only: true,
+            ..settings()
+        };
+        let headers = security_headers(&settings, false);
+        assert!(headers
+            .iter()
+            .any(|(name, _)| name == CONTENT_SECURITY_POLICY_REPORT_ONLY));
+        assert!(!headers
+            .iter()
+            .any(|(name, _)| name == CONTENT_SECURITY_POLICY));
+    }
+
+    #[test]
+    fn hsts_is_only_sent_when_configured() {
+        assert!(!security_headers(&settings(), false)
+            .iter()
+            .any(|(name, _)| name == STRICT_TRANSPORT_SECURITY));
+
+        let settings = SecurityHeadersSettings {
+            hsts_max_age_seconds: Some(31536000),
+            ..settings()
+        };
+        let headers = security_headers(&settings, false);
+        let (_, hsts) = headers
+            .iter()
+            .find(|(name, _)| name == STRICT_TRANSPORT_SECURITY)
+            .unwrap();
+        assert_eq!(hsts, "max-age=31536000; includeSubDomains");
+    }
+
+    #[test]
+    fn inline_styles_can_be_allowed_on_a_response() {
+        let settings = SecurityHeadersSettings {
+            content_security_policy: "default-src 'self'; style-src 'none'; img-src *".into(),
+            ..settings()
+        };
+        let headers = security_headers(&settings, true);
+        let (_, csp) = headers
+            .iter()
+            .find(|(name, _)| name == CONTENT_SECURITY_POLICY)
+            .unwrap();
+        assert_eq!(
+            csp,
+            "default-src 'self'; img-src *; style-src 'self' 'unsafe-inline'; report-uri /csp_report"
+        );
+    }
+}
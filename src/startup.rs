@@ -1,22 +1,40 @@
-use actix_session::{storage::RedisSessionStore, SessionMiddleware};
-use actix_web::{cookie::Key, dev::Server, web, App, HttpServer};
+use actix_session::{config::BrowserSession, storage::RedisSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::{time, Key},
+    dev::Server,
+    web, App, HttpServer,
+};
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
 use actix_web_lab::middleware::from_fn;
+use anyhow::Context;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    authentication::reject_anonymous_users,
+    authentication::{
+        reject_anonymous_users, reject_invalid_api_tokens, reject_invalid_csrf_tokens,
+    },
     configuration::{DatabaseSettings, Settings},
     email_client::EmailClient,
+    email_template::EmailTemplates,
+    jobs::Lane,
     routes::{
-        admin_dashboard, change_password, change_password_form, health_check, home,
-        issue_newsletter, issue_newsletter_form, login, login_form, logout, subscriptions,
-        subscriptions_confirm,
+        active_sessions, admin_dashboard, api_tokens_form, archive, archived_issue, atom_feed,
+        change_password, change_password_form, consent_history, create_api_token, csp_report,
+        data_request, delete_subscriber, erase_personal_data, erase_subscriber,
+        export_consent_events, export_deliveries, export_personal_data, export_subscriber_data,
+        export_subscribers, get_newsletter_issue, health_check, home, import_subscribers,
+        import_subscribers_form, issue_newsletter, issue_newsletter_form, issues_list, login,
+        login_form, logout, logout_everywhere, preview_newsletter, privacy_form,
+        publish_newsletter_issue, request_personal_data, resend_confirmation, revoke_api_token,
+        revoke_session, rss_feed, stylesheet, subscriber_import_report, subscriber_import_result,
+        subscribers_list, subscriptions, subscriptions_confirm, subscriptions_resend,
+        subscriptions_unsubscribe, unsubscribe_subscriber,
     },
+    security_headers::{add_security_headers, CSP_REPORT_PATH},
 };
 
 pub struct Application {
@@ -28,17 +46,11 @@ impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let db_connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
+        // Requests only send emails people are waiting for.
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url.clone(),
-            sender_email,
-            configuration.email_client.authorization_token.clone(),
-            std::time::Duration::from_millis(configuration.email_client.timeout_milliseconds),
-        );
+            .clone()
+            .client(Lane::Transactional);
         tracing::info!("Using email client {:?}", &email_client);
 
         let address = format!(
@@ -49,15 +61,7 @@ impl Application {
 
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
-        let server = run(
-            listener,
-            db_connection_pool,
-            email_client,
-            ApplicationBaseUrl(configuration.application.base_url),
-            HmacSecret(configuration.application.hmac_secret),
-            configuration.redis_uri,
-        )
-        .await?;
+        let server = run(listener, db_connection_pool, email_client, configuration).await?;
 
         Ok(Self { port, server })
     }
@@ -90,54 +94,185 @@ pub struct ApplicationBaseUrl(pub String);
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
+#[derive(Debug)]
+pub struct PrivacyPolicyVersion(pub String);
+
+/// See `ApplicationSettings::trusted_proxies`.
+#[derive(Debug)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+/// Sent once a subscription is confirmed, if configured.
+#[derive(Debug)]
+pub struct WelcomeEmail(pub Option<EmailTemplates>);
+
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: ApplicationBaseUrl,
-    hmac_secret: HmacSecret,
-    redis_uri: Secret<String>,
+    configuration: Settings,
 ) -> Result<Server, anyhow::Error> {
+    let Settings {
+        application,
+        redis_uri,
+        session: session_settings,
+        security_headers: security_headers_settings,
+        subscription_pages,
+        welcome_email,
+        ..
+    } = configuration;
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(base_url);
-    let hmac_secret = web::Data::new(hmac_secret);
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let hmac_secret = web::Data::new(HmacSecret(application.hmac_secret));
+    let privacy_policy_version =
+        web::Data::new(PrivacyPolicyVersion(application.privacy_policy_version));
+    let trusted_proxies = web::Data::new(TrustedProxies(application.trusted_proxies));
+    let subscription_pages = web::Data::new(subscription_pages);
+    let welcome_email = welcome_email
+        .map(|e| e.templates())
+        .transpose()
+        .context("Invalid welcome email")?;
+    let welcome_email = web::Data::new(WelcomeEmail(welcome_email));
     let secret_key = Key::from(hmac_secret.0.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    // Redis does not need to keep the session state around once the session
+    // has reached its absolute timeout.
+    let session_state_ttl = time::Duration::hours(session_settings.absolute_timeout_hours as i64);
+    let session_settings = web::Data::new(session_settings);
+    let security_headers_settings = web::Data::new(security_headers_settings);
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .session_lifecycle(BrowserSession::default().state_ttl(session_state_ttl))
+                    .build(),
+            )
+            .wrap(from_fn(add_security_headers))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check::health_check))
+            .route("/static/style.css", web::get().to(stylesheet))
+            // The feeds must be registered before `{slug}` matches them.
+            .route("/archive", web::get().to(archive))
+            .route("/archive/feed.atom", web::get().to(atom_feed))
+            .route("/archive/feed.rss", web::get().to(rss_feed))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route(CSP_REPORT_PATH, web::post().to(csp_report))
             .route("/subscriptions", web::post().to(subscriptions::subscribe))
+            .route("/privacy", web::get().to(privacy_form))
+            .route("/privacy", web::post().to(request_personal_data))
+            .route("/privacy/request", web::get().to(data_request))
+            .route(
+                "/privacy/request/export",
+                web::get().to(export_personal_data),
+            )
+            .route(
+                "/privacy/request/erase",
+                web::post().to(erase_personal_data),
+            )
             .route(
                 "/subscriptions/confirm",
                 web::get().to(subscriptions_confirm::confirm),
             )
+            .route(
+                "/subscriptions/resend_confirmation",
+                web::post().to(subscriptions_resend::resend_confirmation_email),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(subscriptions_unsubscribe::unsubscribe_form),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::post().to(subscriptions_unsubscribe::unsubscribe),
+            )
             .service(
                 web::scope("/admin")
+                    // Middlewares run in reverse order of registration:
+                    // we must know who the user is before checking their CSRF token.
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/logout", web::get().to(logout))
+                    .route("/logout", web::post().to(logout))
                     .route("/change_password", web::post().to(change_password))
                     .route("/change_password", web::get().to(change_password_form))
                     .route("/newsletter", web::post().to(issue_newsletter))
-                    .route("/newsletter", web::get().to(issue_newsletter_form)),
+                    .route("/newsletter", web::get().to(issue_newsletter_form))
+                    .route("/newsletter/preview", web::post().to(preview_newsletter))
+                    .route("/issues", web::get().to(issues_list))
+                    .route("/sessions", web::get().to(active_sessions))
+                    .route("/sessions/revoke", web::post().to(revoke_session))
+                    .route(
+                        "/sessions/logout_everywhere",
+                        web::post().to(logout_everywhere),
+                    )
+                    .route("/subscribers", web::get().to(subscribers_list))
+                    .route(
+                        "/subscribers/resend_confirmation",
+                        web::post().to(resend_confirmation),
+                    )
+                    .route(
+                        "/subscribers/unsubscribe",
+                        web::post().to(unsubscribe_subscriber),
+                    )
+                    .route("/subscribers/delete", web::post().to(delete_subscriber))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/subscribers/data", web::get().to(export_subscriber_data))
+                    .route("/subscribers/erase", web::post().to(erase_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}/consent",
+                        web::get().to(consent_history),
+                    )
+                    .route(
+                        "/consent_events/export",
+                        web::get().to(export_consent_events),
+                    )
+                    .route("/deliveries/export", web::get().to(export_deliveries))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route(
+                        "/subscribers/imports/{import_id}",
+                        web::get().to(subscriber_import_result),
+                    )
+                    .route(
+                        "/subscribers/imports/{import_id}/errors.csv",
+                        web::get().to(subscriber_import_report),
+                    )
+                    .route("/api_tokens", web::get().to(api_tokens_form))
+                    .route("/api_tokens", web::post().to(create_api_token))
+                    .route("/api_tokens/revoke", web::post().to(revoke_api_token)),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .route(
+                        "/newsletter_issues",
+                        web::post().to(publish_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletter_issues/{newsletter_issue_id}",
+                        web::get().to(get_newsletter_issue),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
             .app_data(hmac_secret.clone())
+            .app_data(privacy_policy_version.clone())
+            .app_data(session_settings.clone())
+            .app_data(security_headers_settings.clone())
+            .app_data(subscription_pages.clone())
+            .app_data(welcome_email.clone())
+            .app_data(trusted_proxies.clone())
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,270 @@
+//! Bulk import of subscribers from a CSV file.
+//!
+//! The file must have a header row with `email` and `name` columns, and can
+//! have a `status` column. Rows are validated like subscriptions made through
+//! the public form; those that cannot be imported end up in an error report.
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::{
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    export::escape_csv_formula,
+};
+
+/// What happens to imported subscribers whose row has no status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// They have already opted in with the previous provider.
+    Confirmed,
+    SendConfirmationEmail,
+}
+
+impl ImportMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "confirmed" => Ok(Self::Confirmed),
+            "send_confirmation_email" => Ok(Self::SendConfirmationEmail),
+            _ => Err(format!("`{}` is not a valid import mode", mode)),
+        }
+    }
+}
+
+/// The status of an imported row, falling back on the import mode.
+fn parse_status(status: &str, mode: ImportMode) -> Result<SubscriptionStatus, String> {
+    match status.to_lowercase().as_str() {
+        "" => Ok(match mode {
+            ImportMode::Confirmed => SubscriptionStatus::Confirmed,
+            ImportMode::SendConfirmationEmail => SubscriptionStatus::PendingConfirmation,
+        }),
+        "confirmed" => Ok(SubscriptionStatus::Confirmed),
+        "pending_confirmation" => Ok(SubscriptionStatus::PendingConfirmation),
+        // People who left the previous provider must not be contacted again.
+        "unsubscribed" => Ok(SubscriptionStatus::Unsubscribed),
+        _ => Err(format!(
+            "`{}` is not a valid status. Use one of: confirmed, pending_confirmation, unsubscribed",
+            status
+        )),
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportedSubscriber {
+    /// Line of the row in the file, to report errors.
+    pub line: u64,
+    pub subscriber: NewSubscriber,
+    pub status: SubscriptionStatus,
+}
+
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub email: String,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct ParsedImport {
+    pub subscribers: Vec<ImportedSubscriber>,
+    pub errors: Vec<RowError>,
+}
+
+#[derive(serde::Deserialize)]
+struct Row {
+    email: String,
+    name: String,
+    #[serde(default)]
+    status: String,
+}
+
+/// Validate every row of `csv`, skipping emails that appear more than once.
+///
+/// Fails only if the file as a whole cannot be read: invalid rows are reported
+/// in `ParsedImport::errors`.
+pub fn parse_csv(csv: &[u8], mode: ImportMode) -> Result<ParsedImport, anyhow::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(csv);
+    let headers = reader
+        .headers()
+        .context("Failed to read the header row")?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect::<csv::StringRecord>();
+    for required in ["email", "name"] {
+        if !headers.iter().any(|h| h == required) {
+            anyhow::bail!("The header row has no `{}` column", required);
+        }
+    }
+
+    let mut subscribers = Vec::new();
+    let mut errors = Vec::new();
+    // Lowercased email -> line where it was first seen
+    let mut seen = HashMap::new();
+    for record in reader.records() {
+        let record = record.context("Failed to read a row")?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let row: Row = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                errors.push(RowError {
+                    line,
+                    email: record.get(0).unwrap_or_default().to_owned(),
+                    name: String::new(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        match validate_row(&row, mode) {
+            Ok((subscriber, status)) => {
+                if let Some(first_line) = seen.get(&row.email.to_lowercase()) {
+                    errors.push(row.error(line, format!("Duplicate of line {}", first_line)));
+                    continue;
+                }
+                seen.insert(row.email.to_lowercase(), line);
+                subscribers.push(ImportedSubscriber {
+                    line,
+                    subscriber,
+                    status,
+                });
+            }
+            Err(reason) => errors.push(row.error(line, reason)),
+        }
+    }
+    Ok(ParsedImport {
+        subscribers,
+        errors,
+    })
+}
+
+fn validate_row(
+    row: &Row,
+    mode: ImportMode,
+) -> Result<(NewSubscriber, SubscriptionStatus), String> {
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(row.email.clone())?,
+        name: SubscriberName::parse(row.name.clone())?,
+    };
+    let status = parse_status(&row.status, mode)?;
+    Ok((subscriber, status))
+}
+
+impl Row {
+    fn error(&self, line: u64, reason: String) -> RowError {
+        RowError {
+            line,
+            email: self.email.clone(),
+            name: self.name.clone(),
+            reason,
+        }
+    }
+}
+
+/// A CSV file with one line per row that was not imported.
+pub fn error_report(errors: &[RowError]) -> Result<String, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["line", "email", "name", "error"])?;
+    for e in errors {
+        let line = e.line.to_string();
+        let record = [line.as_str(), &e.email, &e.name, &e.reason];
+        writer.write_record(record.into_iter().map(escape_csv_formula))?;
+    }
+    let report = writer.into_inner().context("Failed to write the report")?;
+    Ok(String::from_utf8(report)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{error_report, parse_csv, ImportMode};
+    use crate::domain::SubscriptionStatus;
+
+    #[test]
+    fn valid_rows_are_imported() {
+        let csv = "email,name\nursula@example.com,Ursula\nle.guin@example.com,Le Guin\n";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::Confirmed));
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.subscribers.len(), 2);
+        assert_eq!(parsed.subscribers[0].line, 2);
+        assert_eq!(parsed.subscribers[1].subscriber.name.as_ref(), "Le Guin");
+        assert_eq!(parsed.subscribers[1].status, SubscriptionStatus::Confirmed);
+    }
+
+    #[test]
+    fn the_status_column_overrides_the_import_mode() {
+        let csv = "Name,Email,Status\n\
+            Ursula,ursula@example.com,\n\
+            Le Guin,le.guin@example.com,unsubscribed\n";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::SendConfirmationEmail));
+        assert_eq!(
+            parsed.subscribers[0].status,
+            SubscriptionStatus::PendingConfirmation
+        );
+        assert_eq!(
+            parsed.subscribers[1].status,
+            SubscriptionStatus::Unsubscribed
+        );
+    }
+
+    #[test]
+    fn invalid_rows_are_reported() {
+        let csv = "email,name,status\n\
+            not-an-email,Ursula,\n\
+            ursula@example.com,,\n\
+            le.guin@example.com,Le Guin,gone\n\
+            short-row@example.com\n";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::Confirmed));
+        assert!(parsed.subscribers.is_empty());
+        let lines: Vec<u64> = parsed.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+        assert!(parsed.errors[0]
+            .reason
+            .contains("not a valid subscriber email"));
+        assert!(parsed.errors[2]
+            .reason
+            .contains("`gone` is not a valid status"));
+    }
+
+    #[test]
+    fn duplicate_emails_are_only_imported_once() {
+        let csv = "email,name\nursula@example.com,Ursula\nUrsula@Example.com,Ursula K.\n";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::Confirmed));
+        assert_eq!(parsed.subscribers.len(), 1);
+        assert_eq!(parsed.errors[0].line, 3);
+        assert_eq!(parsed.errors[0].reason, "Duplicate of line 2");
+    }
+
+    #[test]
+    fn files_without_the_required_columns_are_rejected() {
+        assert_err!(parse_csv(
+            "email,full_name\nursula@example.com,Ursula\n".as_bytes(),
+            ImportMode::Confirmed
+        ));
+    }
+
+    #[test]
+    fn the_error_report_is_a_csv_file() {
+        let csv = "email,name\n\"not, an email\",Ursula\n";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::Confirmed));
+        let report = assert_ok!(error_report(&parsed.errors));
+        assert_eq!(
+            report,
+            "line,email,name,error\n\
+            2,\"not, an email\",Ursula,\"not, an email is not a valid subscriber email\"\n"
+        );
+    }
+
+    #[test]
+    fn formulas_in_the_error_report_are_escaped() {
+        let csv = "email,name
+=1+1,@SUM(A1)
+";
+        let parsed = assert_ok!(parse_csv(csv.as_bytes(), ImportMode::Confirmed));
+        let report = assert_ok!(error_report(&parsed.errors));
+        assert!(report.starts_with("line,email,name,error\n2,'=1+1,'@SUM(A1),"));
+    }
+}
@@ -1,5 +1,10 @@
-use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+
+use crate::startup::TrustedProxies;
 
 // Return an opaque 500 while preserving the error root's cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -21,3 +26,55 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Render a template as the body of a `200 OK` HTML response.
+pub fn html_page<T: Template>(template: &T) -> Result<HttpResponse, actix_web::Error> {
+    let body = template.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// The IP address of the client. `Forwarded` and `X-Forwarded-For` can be
+/// set by anyone: they are only honoured on requests from a trusted proxy.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer_ip = request.peer_addr().map(|a| a.ip());
+    let from_trusted_proxy = match (peer_ip, request.app_data::<web::Data<TrustedProxies>>()) {
+        (Some(ip), Some(proxies)) => proxies.0.contains(&ip),
+        _ => false,
+    };
+    if from_trusted_proxy {
+        request
+            .connection_info()
+            .realip_remote_addr()
+            .map(str::to_owned)
+    } else {
+        peer_ip.map(|ip| ip.to_string())
+    }
+}
+
+/// A bound of a date filter, `None` when it is left open.
+type Bound = Option<DateTime<Utc>>;
+
+/// Parse the `YYYY-MM-DD` bounds of a date filter, both inclusive, into
+/// `[from, before)` timestamps. Empty bounds are left open.
+pub fn parse_date_range(from: &str, until: &str) -> Result<(Bound, Bound), anyhow::Error> {
+    let from = parse_date(from)?.map(start_of_day);
+    let before = parse_date(until)?
+        .and_then(|d| d.checked_add_days(Days::new(1)))
+        .map(start_of_day);
+    Ok((from, before))
+}
+
+fn parse_date(date: &str) -> Result<Option<NaiveDate>, anyhow::Error> {
+    if date.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("`{}` is not a valid date", date))?;
+    Ok(Some(date))
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_utc(date.and_time(NaiveTime::MIN), Utc)
+}
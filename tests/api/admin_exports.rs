@@ -0,0 +1,144 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+
+async fn subscribe(app: &TestApp, name: &str, email: &str) {
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_data() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let subscribers = app.get_admin_export("/subscribers/export?format=csv").await;
+    let deliveries = app.get_admin_export("/deliveries/export?format=csv").await;
+
+    // Then
+    assert_is_redirect_to(&subscribers, "/login");
+    assert_is_redirect_to(&deliveries, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, "Alice", "alice@example.com").await;
+    subscribe(&app, "Bob", "bob@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app.get_admin_export("/subscribers/export?format=csv").await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .contains("subscribers.csv"));
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "id,email,name,status,subscribed_at");
+    assert!(lines[1].contains("alice@example.com,Alice,pending_confirmation,"));
+    assert!(lines[2].contains("bob@example.com,Bob,pending_confirmation,"));
+}
+
+#[tokio::test]
+async fn subscriber_exports_use_the_same_filters_as_the_subscriber_list() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, "Alice", "alice@example.com").await;
+    subscribe(&app, "Bob", "bob@example.com").await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed' WHERE name = 'Bob'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .get_admin_export("/subscribers/export?format=jsonl&status=confirmed")
+        .await;
+
+    // Then
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["email"], "bob@example.com");
+    assert_eq!(rows[0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn exports_reject_invalid_parameters() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let bad_format = app.get_admin_export("/subscribers/export?format=xml").await;
+    let bad_date = app
+        .get_admin_export("/deliveries/export?format=csv&attempted_from=yesterday")
+        .await;
+
+    // Then
+    assert_eq!(bad_format.status().as_u16(), 400);
+    assert_eq!(bad_date.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn delivery_attempts_are_exported() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_admin().await;
+    mount_email_server(&app).await;
+    app.publish_issue("First issue", "Hello", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // When
+    let response = app
+        .get_admin_export("/deliveries/export?format=jsonl")
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["issue_title"], "First issue");
+    assert_eq!(rows[0]["outcome"], "sent");
+}
@@ -0,0 +1,325 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp, name: &str, email: &str) -> Uuid {
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response_get = app.get_admin_subscribers("").await;
+    let response_post = app
+        .post_admin_subscriber_action("delete", Uuid::new_v4())
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response_get, "/login");
+    assert_is_redirect_to(&response_post, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, "Alice", "alice@example.com").await;
+    subscribe(&app, "Bob", "bob@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let by_email = app.get_admin_subscribers_html("?q=alice@").await;
+    let by_name = app.get_admin_subscribers_html("?q=bob").await;
+
+    // Then
+    assert!(by_email.contains("alice@example.com"));
+    assert!(!by_email.contains("bob@example.com"));
+    assert!(by_name.contains("bob@example.com"));
+    assert!(!by_name.contains("alice@example.com"));
+}
+
+#[tokio::test]
+async fn search_terms_are_not_wildcards() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let html_page = app.get_admin_subscribers_html("?q=%25").await;
+
+    // Then
+    assert!(!html_page.contains("alice@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_subscription_date() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let alice = subscribe(&app, "Alice", "alice@example.com").await;
+    subscribe(&app, "Bob", "bob@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed', subscribed_at = '2020-01-15T12:00:00Z' \
+        WHERE id = $1",
+        alice
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login_admin().await;
+
+    // When
+    let confirmed = app.get_admin_subscribers_html("?status=confirmed").await;
+    let january_2020 = app
+        .get_admin_subscribers_html("?subscribed_from=2020-01-01&subscribed_until=2020-01-15")
+        .await;
+    let since_2021 = app
+        .get_admin_subscribers_html("?subscribed_from=2021-01-01")
+        .await;
+
+    // Then
+    assert!(confirmed.contains("alice@example.com"));
+    assert!(!confirmed.contains("bob@example.com"));
+    assert!(january_2020.contains("alice@example.com"));
+    assert!(!january_2020.contains("bob@example.com"));
+    assert!(since_2021.contains("bob@example.com"));
+    assert!(!since_2021.contains("alice@example.com"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    for query in ["?status=gone", "?subscribed_from=yesterday"] {
+        // When
+        let response = app.get_admin_subscribers(query).await;
+
+        // Then
+        assert_eq!(response.status().as_u16(), 400, "Query: {}", query);
+    }
+}
+
+#[tokio::test]
+async fn the_subscriber_list_is_paginated() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    for i in 0..51 {
+        subscribe(&app, "Subscriber", &format!("subscriber{}@example.com", i)).await;
+    }
+    app.login_admin().await;
+
+    // When
+    let first_page = app.get_admin_subscribers_html("?q=subscriber").await;
+    let second_page = app.get_admin_subscribers_html("?q=subscriber&page=2").await;
+
+    // Then
+    assert_eq!(first_page.matches("@example.com</td>").count(), 50);
+    assert!(first_page.contains("q=subscriber"));
+    assert_eq!(second_page.matches("@example.com</td>").count(), 1);
+}
+
+#[tokio::test]
+async fn subscriber_list_pages_out_of_range_are_clamped() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .get_admin_subscribers(&format!("?page={}", i64::MAX))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirmation_emails_can_be_sent_again() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let alice = subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_admin_subscriber_action("resend_confirmation", alice)
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("The confirmation email has been sent again"));
+    app.dispatch_all_pending_emails().await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), 2);
+    // The new link confirms the subscription
+    let confirmation_links = app.get_confirmation_links(&email_requests[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let status = sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", alice)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_unsubscribed_by_an_admin() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let alice = subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app.post_admin_subscriber_action("unsubscribe", alice).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let status = sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", alice)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_deleted_along_with_their_tokens() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let alice = subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app.post_admin_subscriber_action("delete", alice).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("The subscriber has been deleted"));
+    assert!(!html_page.contains("alice@example.com"));
+    let n_tokens = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM subscription_tokens WHERE subscriber_id = $1",
+        alice
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn acting_on_an_unknown_subscriber_is_reported() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_admin_subscriber_action("delete", Uuid::new_v4())
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("Unknown subscriber"));
+}
+
+#[tokio::test]
+async fn personal_data_can_be_exported_by_an_admin() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/data?email=Alice@example.com",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["name"], "Alice");
+    assert_eq!(data["subscription"]["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn personal_data_can_be_erased_by_an_admin() {
+    // Given
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let alice = subscribe(&app, "Alice", "alice@example.com").await;
+    app.login_admin().await;
+
+    // When
+    let response = app.post_admin_erase_subscriber("alice@example.com").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("Everything stored about alice@example.com has been erased"));
+    let n_tokens = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM subscription_tokens WHERE subscriber_id = $1",
+        alice
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_tokens, 0);
+    let tombstone = sqlx::query!("SELECT email_digest FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // The address itself is not kept.
+    assert!(!tombstone.email_digest.contains("alice"));
+}
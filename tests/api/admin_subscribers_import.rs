@@ -0,0 +1,375 @@
+use claims::assert_matches;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::jobs::{try_execute_job, ExecutionOutcome, Lane};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Follow the redirect to the outcome of the import.
+async fn import_outcome(app: &TestApp, response: reqwest::Response) -> (String, String) {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let result_page = app
+        .api_client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let report = app
+        .api_client
+        .get(format!("{}{}/errors.csv", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    (result_page, report)
+}
+
+async fn subscriber_statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .post_import_subscribers("email,name\nursula@example.com,Ursula\n", "confirmed")
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn imports_without_the_csrf_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", app.address))
+        .header("Content-Type", "multipart/form-data; boundary=b")
+        .body("--b--\r\n")
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn imports_with_the_csrf_token_in_the_query_string_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let csrf_token = app.csrf_token().await.unwrap();
+    let body = "--b\r\n\
+        Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+        pending\r\n\
+        --b\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        email,name\nursula@example.com,Ursula\r\n\
+        --b--\r\n";
+
+    // When
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/subscribers/import?csrf_token={}",
+            app.address, csrf_token
+        ))
+        .header("Content-Type", "multipart/form-data; boundary=b")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn imports_can_send_the_csrf_token_in_a_header() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let csrf_token = app.csrf_token().await.unwrap();
+    let body = "--b\r\n\
+        Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+        confirmed\r\n\
+        --b\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        email,name\nursula@example.com,Ursula\r\n\
+        --b--\r\n";
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", app.address))
+        .header("Content-Type", "multipart/form-data; boundary=b")
+        .header("X-CSRF-Token", csrf_token)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    let (result_page, _) = import_outcome(&app, response).await;
+    assert!(result_page.contains("1 subscriber(s) imported."));
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![("ursula@example.com".into(), "confirmed".into())]
+    );
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_marked_as_confirmed() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name,status\n\
+        ursula@example.com,Ursula,\n\
+        le.guin@example.com,Le Guin,unsubscribed\n";
+
+    // When
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // Then
+    let (result_page, _) = import_outcome(&app, response).await;
+    assert!(result_page.contains("2 subscriber(s) imported."));
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![
+            ("le.guin@example.com".into(), "unsubscribed".into()),
+            ("ursula@example.com".into(), "confirmed".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_sent_a_confirmation_email() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_import_subscribers(
+            "email,name\nursula@example.com,Ursula\n",
+            "send_confirmation_email",
+        )
+        .await;
+
+    // Then
+    import_outcome(&app, response).await;
+    // Sent by the bulk workers, in the background.
+    assert_matches!(
+        try_execute_job(&app.jobs, Lane::Transactional).await,
+        Ok(ExecutionOutcome::EmptyQueue)
+    );
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        subscriber_statuses(&app).await,
+        vec![("ursula@example.com".into(), "confirmed".into())]
+    );
+}
+
+#[tokio::test]
+async fn invalid_and_duplicate_rows_are_in_the_error_report() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula\n", "confirmed")
+        .await;
+    let csv = "email,name\n\
+        URSULA@example.com,Ursula\n\
+        not-an-email,Someone\n\
+        le.guin@example.com,Le Guin\n\
+        le.guin@example.com,Le Guin\n";
+
+    // When
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // Then
+    let (result_page, report) = import_outcome(&app, response).await;
+    assert!(result_page.contains("1 subscriber(s) imported."));
+    assert!(result_page.contains("3 row(s) could not be imported"));
+    assert_eq!(
+        report,
+        "line,email,name,error\n\
+        2,URSULA@example.com,Ursula,Already subscribed\n\
+        3,not-an-email,Someone,not-an-email is not a valid subscriber email\n\
+        5,le.guin@example.com,Le Guin,Duplicate of line 4\n"
+    );
+    assert_eq!(subscriber_statuses(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn people_who_subscribe_during_an_import_are_in_the_error_report() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    // Subscribes once existing subscribers were looked for, as the import is saved.
+    for query in [
+        "CREATE FUNCTION subscribe_during_import() RETURNS trigger AS $$
+        BEGIN
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES (gen_random_uuid(), 'le.guin@example.com', 'Le Guin', now(), 'confirmed', 'token');
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql",
+        "CREATE TRIGGER subscribe_during_import AFTER INSERT ON subscriber_imports
+        FOR EACH ROW EXECUTE FUNCTION subscribe_during_import()",
+    ] {
+        sqlx::query(query).execute(&app.db_pool).await.unwrap();
+    }
+    let csv = "email,name\n\
+        ursula@example.com,Ursula\n\
+        le.guin@example.com,Le Guin\n";
+
+    // When
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // Then
+    let (result_page, report) = import_outcome(&app, response).await;
+    assert!(result_page.contains("1 subscriber(s) imported."));
+    assert!(result_page.contains("1 row(s) could not be imported"));
+    assert_eq!(
+        report,
+        "line,email,name,error\n\
+        3,le.guin@example.com,Le Guin,Already subscribed\n"
+    );
+}
+
+#[tokio::test]
+async fn erased_subscribers_are_not_imported_again() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula\n", "confirmed")
+        .await;
+    app.post_admin_erase_subscriber("ursula@example.com").await;
+
+    // When
+    let response = app
+        .post_import_subscribers("email,name\nUrsula@Example.com,Ursula\n", "confirmed")
+        .await;
+
+    // Then
+    let (result_page, report) = import_outcome(&app, response).await;
+    assert!(result_page.contains("0 subscriber(s) imported."));
+    assert_eq!(
+        report,
+        "line,email,name,error\n\
+        2,Ursula@Example.com,Ursula,Erased on request: the address cannot be imported again\n"
+    );
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn files_that_cannot_be_read_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_import_subscribers("mail,full_name\nursula@example.com,Ursula\n", "confirmed")
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/subscribers/import", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The header row has no `email` column"));
+    assert!(subscriber_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn forms_larger_than_the_limit_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let csrf_token = app.csrf_token().await.unwrap();
+    // Many fields, each under the limit of the file.
+    let field = format!(
+        "--b\r\nContent-Disposition: form-data; name=\"padding\"\r\n\r\n{}\r\n",
+        "x".repeat(1024 * 1024)
+    );
+    let body = format!("{}--b--\r\n", field.repeat(21));
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", app.address))
+        .header("Content-Type", "multipart/form-data; boundary=b")
+        .header("X-CSRF-Token", csrf_token)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/subscribers/import", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The form is too large"));
+}
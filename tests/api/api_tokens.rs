@@ -0,0 +1,272 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::newsletter::create_confirmed_subscriber;
+
+fn issue_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_tokens() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response_get = app.get_api_tokens().await;
+    let response_post = app
+        .post_create_api_token(&serde_json::json!({ "name": "CMS", "issues:write": "on" }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response_get, "/login");
+    assert_is_redirect_to(&response_post, "/login");
+}
+
+#[tokio::test]
+async fn api_tokens_are_stored_hashed_and_listed() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let token = app.create_api_token(&["issues:write"]).await;
+
+    // Then
+    assert!(token.starts_with("z2p_"));
+    let saved = sqlx::query!("SELECT name, token_hash, scopes FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "CMS");
+    assert_ne!(saved.token_hash, token);
+    assert_eq!(saved.scopes, vec!["issues:write".to_string()]);
+
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("CMS"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn an_api_token_needs_at_least_one_scope() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_create_api_token(&serde_json::json!({ "name": "CMS" }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("Select at least one scope"));
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_bearer_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let missing = app
+        .api_client
+        .post(format!("{}/api/v1/newsletter_issues", &app.address))
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+    let invalid = app
+        .post_api_newsletter_issue("z2p_not-a-real-token", Some("key"), &issue_body())
+        .await;
+
+    // Then
+    assert_eq!(missing.status().as_u16(), 401);
+    assert!(missing.headers().contains_key("WWW-Authenticate"));
+    assert_eq!(invalid.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn issues_published_through_the_api_are_delivered() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:read", "issues:write"]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletter_issue(&token, Some("first-issue"), &issue_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let status_url = body["status_url"].as_str().unwrap().to_owned();
+
+    let status: serde_json::Value = app
+        .get_api_newsletter_issue(&token, &status_url)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["status"], "delivering");
+    assert_eq!(status["pending_deliveries"], 1);
+
+    app.dispatch_all_pending_emails().await;
+
+    let status: serde_json::Value = app
+        .get_api_newsletter_issue(&token, &status_url)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["status"], "delivered");
+    assert_eq!(status["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn api_publishing_is_idempotent() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:write"]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let first = app
+        .post_api_newsletter_issue(&token, Some("same-key"), &issue_body())
+        .await;
+    let second = app
+        .post_api_newsletter_issue(&token, Some("same-key"), &issue_body())
+        .await;
+
+    // Then
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn api_publishing_requires_an_idempotency_key() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:write"]).await;
+
+    // When
+    let response = app
+        .post_api_newsletter_issue(&token, None, &issue_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn api_publishing_rejects_unknown_template_variables() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    let mut body = issue_body();
+    body["content_text"] = "Hi {{ first_name }}!".into();
+
+    // When
+    let response = app
+        .post_api_newsletter_issue(&token, Some("key"), &body)
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("first_name"));
+}
+
+#[tokio::test]
+async fn api_publishing_sanitizes_html_and_reports_warnings() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    let mut body = issue_body();
+    body["content_html"] = "<p>Hello</p><script>alert(1)</script>".into();
+
+    // When
+    let response = app
+        .post_api_newsletter_issue(&token, Some("key"), &body)
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["warnings"][0].as_str().unwrap().contains("script"));
+    let saved = sqlx::query!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.html_content, "<p>Hello</p>");
+}
+
+#[tokio::test]
+async fn tokens_can_only_be_used_within_their_scopes() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:read"]).await;
+
+    // When
+    let response = app
+        .post_api_newsletter_issue(&token, Some("key"), &issue_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    // When
+    let response = app
+        .post_revoke_api_token(&serde_json::json!({ "token_id": token_id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+
+    // Then
+    let response = app
+        .post_api_newsletter_issue(&token, Some("key"), &issue_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
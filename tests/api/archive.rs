@@ -0,0 +1,308 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+
+async fn slug_of(app: &TestApp, title: &str) -> String {
+    sqlx::query!("SELECT slug FROM newsletter_issues WHERE title = $1", title)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug
+}
+
+#[tokio::test]
+async fn the_archive_only_lists_public_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Public issue", "Hello world!", true)
+        .await;
+    app.publish_issue("Private issue", "Members only", false)
+        .await;
+
+    // When
+    let html_page = app.get_archive_html().await;
+
+    // Then
+    let slug = slug_of(&app, "Public issue").await;
+    assert!(slug.starts_with("public-issue-"));
+    assert!(html_page.contains(&format!(r#"<a href="/archive/{}">Public issue</a>"#, slug)));
+    assert!(!html_page.contains("Private issue"));
+}
+
+#[tokio::test]
+async fn public_issues_have_their_own_page() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Public issue", "Hi {{ name }}, some **news**!", true)
+        .await;
+    let slug = slug_of(&app, "Public issue").await;
+
+    // When
+    let response = app.get_archive(&format!("/{}", slug)).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Public issue</h1>"));
+    assert!(html_page.contains("Hi reader, some <strong>news</strong>!"));
+}
+
+#[tokio::test]
+async fn public_issues_do_not_link_anonymous_readers_to_an_unsubscribe_page() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue(
+        "Public issue",
+        r#"Some news. <a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        true,
+    )
+    .await;
+    let slug = slug_of(&app, "Public issue").await;
+
+    // When
+    let html_page = app
+        .get_archive(&format!("/{}", slug))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Then
+    assert!(html_page.contains(r#"<a href="" rel="noopener noreferrer">Unsubscribe</a>"#));
+}
+
+#[tokio::test]
+async fn private_issues_are_not_found_in_the_archive() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Private issue", "Members only", false)
+        .await;
+    let slug = slug_of(&app, "Private issue").await;
+
+    // When
+    let private_issue = app.get_archive(&format!("/{}", slug)).await;
+    let missing_issue = app.get_archive("/no-such-issue").await;
+
+    // Then
+    assert_eq!(private_issue.status().as_u16(), 404);
+    assert_eq!(missing_issue.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_public_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Public issue", "Some **news**", true)
+        .await;
+    app.publish_issue("Private issue", "Members only", false)
+        .await;
+    let slug = slug_of(&app, "Public issue").await;
+
+    // When
+    let response = app.get_archive("/feed.atom").await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Public issue</title>"));
+    assert!(feed.contains(&format!(
+        r#"<link href="{}/archive/{}"/>"#,
+        app.base_url, slug
+    )));
+    // The HTML content is escaped inside the XML document
+    assert!(feed.contains("&lt;strong&gt;news&lt;/strong&gt;"));
+    assert!(!feed.contains("Private issue"));
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_public_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Public issue", "Some news", true).await;
+    app.publish_issue("Private issue", "Members only", false)
+        .await;
+
+    // When
+    let response = app.get_archive("/feed.rss").await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Public issue</title>"));
+    assert!(!feed.contains("Private issue"));
+}
+
+#[tokio::test]
+async fn the_archive_can_be_searched() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Gardening tips", "How to grow tomatoes on a balcony", true)
+        .await;
+    app.publish_issue("Cooking", "A recipe for soup", true)
+        .await;
+    app.publish_issue("Secret tomatoes", "Private tomatoes", false)
+        .await;
+
+    // When
+    let html_page = app.get_archive("?q=tomato").await.text().await.unwrap();
+
+    // Then
+    assert!(html_page.contains("Gardening tips"));
+    assert!(html_page.contains("<mark>tomatoes</mark>"));
+    assert!(!html_page.contains("Cooking"));
+    assert!(!html_page.contains("Secret tomatoes"));
+}
+
+#[tokio::test]
+async fn search_results_are_ranked_by_relevance() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Rust news", "Rust, Rust and more Rust", true)
+        .await;
+    app.publish_issue("Weekly digest", "A word about rust", true)
+        .await;
+
+    // When
+    let html_page = app.get_archive("?q=rust").await.text().await.unwrap();
+
+    // Then
+    let rust_news = html_page.find("Rust news").unwrap();
+    let weekly_digest = html_page.find("Weekly digest").unwrap();
+    assert!(rust_news < weekly_digest);
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    for i in 0..21 {
+        app.publish_issue(&format!("Issue number {}", i), "Hello", true)
+            .await;
+    }
+
+    // When
+    let first_page = app.get_archive("").await.text().await.unwrap();
+    let second_page = app.get_archive("?page=2").await.text().await.unwrap();
+
+    // Then
+    assert_eq!(first_page.matches("Issue number").count(), 20);
+    assert!(first_page.contains("page=2"));
+    assert_eq!(second_page.matches("Issue number").count(), 1);
+    // Issues are listed from the most recent
+    assert!(second_page.contains("Issue number 0<"));
+}
+
+#[tokio::test]
+async fn archive_pages_out_of_range_are_clamped() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_archive(&format!("?page={}", i64::MAX)).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_admin_issue_list() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_admin_issues("").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_admin_issue_list_includes_private_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    app.publish_issue("Public tomatoes", "Tomatoes for everyone", true)
+        .await;
+    app.publish_issue("Private tomatoes", "Tomatoes for us", false)
+        .await;
+    app.publish_issue("Cooking", "A recipe for soup", false)
+        .await;
+
+    // When
+    let html_page = app
+        .get_admin_issues("?q=tomatoes")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Then
+    assert!(html_page.contains("Public tomatoes"));
+    assert!(html_page.contains("Private tomatoes"));
+    assert!(!html_page.contains("Cooking"));
+}
+
+#[tokio::test]
+async fn the_admin_issue_list_shows_the_author_and_delivery_status() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_admin().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.publish_issue("Newsletter title", "Hello", false).await;
+
+    // When - Part 1 - Before delivery
+    let html_page = app.get_admin_issues("").await.text().await.unwrap();
+
+    // Then
+    assert!(html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+    assert!(html_page.contains("<td>delivering</td>"));
+
+    // When - Part 2 - After delivery
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_admin_issues("").await.text().await.unwrap();
+
+    // Then
+    assert!(html_page.contains("<td>delivered</td>"));
+}
+
+#[tokio::test]
+async fn issues_without_recipients_are_delivered_right_away() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    app.publish_issue("Newsletter title", "Hello", false).await;
+
+    // Then
+    let issue = sqlx::query!("SELECT status, author_user_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "delivered");
+    assert_eq!(issue.author_user_id, Some(app.test_user.user_id));
+}
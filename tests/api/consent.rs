@@ -0,0 +1,220 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+
+struct RecordedEvent {
+    action: String,
+    source: String,
+    privacy_policy_version: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+async fn consent_events(app: &TestApp) -> Vec<RecordedEvent> {
+    sqlx::query_as!(
+        RecordedEvent,
+        r#"
+SELECT action, source, privacy_policy_version, ip_address, user_agent
+FROM consent_events
+ORDER BY occurred_at
+"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_records_a_consent_event() {
+    // Given
+    let app = spawn_app().await;
+    let _mock_guard = wiremock::Mock::given(wiremock::matchers::any())
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // When
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "Newsletter reader")
+        .form(&serde_json::json!({
+            "name": "Ursula",
+            "email": "ursula@example.com",
+            "source": "footer",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Then
+    let events = consent_events(&app).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, "subscribed");
+    assert_eq!(events[0].source, "footer");
+    assert_eq!(events[0].privacy_policy_version, "2023-08-04");
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(events[0].user_agent.as_deref(), Some("Newsletter reader"));
+}
+
+async fn subscribe_with_forwarded_for(app: &TestApp) {
+    let _mock_guard = wiremock::Mock::given(wiremock::matchers::any())
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&serde_json::json!({
+            "name": "Ursula",
+            "email": "ursula@example.com",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn forwarded_ip_addresses_are_ignored_by_default() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    subscribe_with_forwarded_for(&app).await;
+
+    // Then
+    let events = consent_events(&app).await;
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn forwarded_ip_addresses_are_recorded_behind_a_trusted_proxy() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+
+    // When
+    subscribe_with_forwarded_for(&app).await;
+
+    // Then
+    let events = consent_events(&app).await;
+    assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn confirming_and_unsubscribing_record_consent_events() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    // When
+    app.post_unsubscribe(&unsubscribe_token).await;
+
+    // Then
+    let events = consent_events(&app).await;
+    let actions: Vec<(&str, &str)> = events
+        .iter()
+        .map(|e| (e.action.as_str(), e.source.as_str()))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            ("subscribed", "subscription_form"),
+            ("confirmed", "confirmation_link"),
+            ("unsubscribed", "unsubscribe_link"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn consent_events_cannot_be_changed() {
+    // Given
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // When
+    let update = sqlx::query!("UPDATE consent_events SET source = 'tampered'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM consent_events")
+        .execute(&app.db_pool)
+        .await;
+
+    // Then
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(consent_events(&app).await[0].source, "subscription_form");
+}
+
+#[tokio::test]
+async fn the_consent_history_of_a_subscriber_can_be_viewed_and_exported() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.login_admin().await;
+
+    // When
+    let html_page = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/{}/consent",
+            app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let csv = app
+        .get_admin_export(&format!(
+            "/consent_events/export?format=csv&subscriber_id={}",
+            subscriber_id
+        ))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Then
+    assert!(html_page.contains("confirmation_link"));
+    assert!(html_page.contains("2023-08-04"));
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("subscriber_id,email,action,source"));
+    assert!(lines[2].contains(",confirmed,confirmation_link,2023-08-04,127.0.0.1,"));
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_deletes_their_consent_events() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    app.login_admin().await;
+
+    // When
+    let response = app.post_admin_erase_subscriber(&email).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert!(consent_events(&app).await.is_empty());
+}
@@ -0,0 +1,137 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn admin_forms_embed_a_csrf_token() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let pages = vec![
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_publish_newsletter_html().await,
+        app.get_sessions_html().await,
+        app.get_api_tokens_html().await,
+    ];
+
+    // Then
+    let token = app.csrf_token().await.unwrap();
+    for html_page in pages {
+        assert!(html_page.contains(&format!(r#"name="csrf_token" value="{}""#, token)));
+    }
+}
+
+#[tokio::test]
+async fn admin_posts_without_a_csrf_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/change_password", &app.address))
+        .form(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": "new-random-password",
+            "new_password_confirmation": "new-random-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn admin_posts_with_a_wrong_csrf_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "content_markdown": "Newsletter body as **Markdown**",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "csrf_token": "not-the-session-token",
+        }))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn multipart_posts_without_a_csrf_token_header_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let csrf_token = app.csrf_token().await.unwrap();
+    let body = format!(
+        "--b\r\n\
+        Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+        {csrf_token}\r\n\
+        --b\r\n\
+        Content-Disposition: form-data; name=\"current_password\"\r\n\r\n\
+        {}\r\n\
+        --b--\r\n",
+        app.test_user.password
+    );
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/change_password", &app.address))
+        .header("Content-Type", "multipart/form-data; boundary=b")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_csrf_token_can_be_sent_as_a_header() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let token = app.csrf_token().await.unwrap();
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", token)
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logging_out_requires_a_post() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_ne!(response.status().as_u16(), 303);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
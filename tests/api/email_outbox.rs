@@ -0,0 +1,159 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+async fn subscribe(app: &TestApp) -> reqwest::Response {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+}
+
+async fn n_queued_emails(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM background_jobs"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+/// Make the queued emails due now, as if their retry delay had passed.
+async fn make_queued_emails_due(app: &TestApp) {
+    sqlx::query!("UPDATE background_jobs SET run_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_queues_the_confirmation_email_instead_of_sending_it() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = subscribe(&app).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(n_queued_emails(&app).await, 1);
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_queued_emails(&app).await, 0);
+}
+
+#[tokio::test]
+async fn subscribing_succeeds_while_the_email_api_is_down() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = subscribe(&app).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_queued_emails(&app).await, 1);
+}
+
+#[tokio::test]
+async fn failed_emails_are_retried_later() {
+    // Given
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    app.dispatch_all_pending_emails().await;
+    let retry = sqlx::query!("SELECT n_attempts, run_at > now() AS delayed FROM background_jobs")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    make_queued_emails_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(retry.n_attempts, 1);
+    assert_eq!(retry.delayed, Some(true));
+    assert_eq!(n_queued_emails(&app).await, 0);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&requests[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn emails_are_given_up_on_after_the_last_attempt() {
+    // Given
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE background_jobs SET n_attempts = max_attempts - 1")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(n_queued_emails(&app).await, 0);
+}
+
+#[tokio::test]
+async fn queued_confirmation_emails_are_skipped_once_confirmed() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(n_queued_emails(&app).await, 0);
+}
@@ -9,7 +9,7 @@ async fn health_check_works() {
 
     // When
     let response = client
-        .get(&format!("{}/health_check", &test_app.address))
+        .get(format!("{}/health_check", &test_app.address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -3,9 +3,9 @@ use once_cell::sync::Lazy;
 use sqlx::{types::Uuid, Connection, Executor, PgConnection, PgPool};
 use wiremock::MockServer;
 use zero2prod::{
-    configuration::{self, get_configuration},
-    email_client::EmailClient,
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    configuration::{self, get_configuration, Settings},
+    jobs::{try_execute_job, ExecutionOutcome, JobContext, Lane},
+    pending_subscriptions::{purge_stale_pending_subscriptions, try_send_reminder},
     startup::{get_connection_pool, Application},
     telemetry,
 };
@@ -32,7 +32,9 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub base_url: String,
+    /// What the background worker hands to the jobs it runs.
+    pub jobs: JobContext,
 }
 
 pub struct TestUser {
@@ -50,6 +52,11 @@ pub struct ConfirmationLinks {
 /// Spin up an instance of our application
 /// and returns its address (i.e. http://localhost:XXXX)
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, with settings adjusted by `configure`.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed
     // All other invocations will instead skip execution
     Lazy::force(&TRACING);
@@ -65,6 +72,7 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        configure(&mut c);
         c
     };
 
@@ -75,12 +83,8 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    tokio::spawn(application.run_until_stopped());
+    let client = new_api_client();
 
     let test_app = TestApp {
         address,
@@ -89,13 +93,33 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url.clone(),
+        jobs: JobContext {
+            pool: get_connection_pool(&configuration.database),
+            transactional_email_client: configuration
+                .email_client
+                .clone()
+                .client(Lane::Transactional),
+            bulk_email_client: configuration.email_client.client(Lane::Bulk),
+            base_url: configuration.application.base_url,
+            pending_subscriptions: configuration.pending_subscriptions,
+            welcome_email: configuration.welcome_email.map(|e| e.templates().unwrap()),
+        },
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
+/// A client with its own cookie store, as if the user was on another device.
+pub fn new_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 impl TestApp {
     pub async fn login_admin(&self) {
         let username = &self.test_user.username;
@@ -110,21 +134,68 @@ impl TestApp {
         assert_is_redirect_to(&response, "/admin/dashboard");
     }
 
+    /// The CSRF token of the current session, as embedded in the admin forms.
+    /// `None` if the client is not logged in.
+    pub async fn csrf_token(&self) -> Option<String> {
+        let html_page = self.get_admin_dashboard_html().await;
+        let marker = r#"name="csrf_token" value=""#;
+        let start = html_page.find(marker)? + marker.len();
+        let end = start + html_page[start..].find('"')?;
+        Some(html_page[start..end].to_owned())
+    }
+
+    /// Add the CSRF token of the current session to a form body,
+    /// unless the body already provides one.
+    pub async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        if body.get("csrf_token").is_none() {
+            if let Some(token) = self.csrf_token().await {
+                body["csrf_token"] = token.into();
+            }
+        }
+        body
+    }
+
+    /// Run the jobs that are due, as the background worker would.
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
+            // Bulk workers run jobs of both lanes.
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+                try_execute_job(&self.jobs, Lane::Bulk).await.unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn send_all_pending_reminders(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_send_reminder(
+                &self.jobs.pool,
+                self.jobs.email_client(Lane::Transactional),
+                &self.jobs.base_url,
+                &self.jobs.pending_subscriptions,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    pub async fn purge_stale_pending_subscriptions(&self) -> u64 {
+        purge_stale_pending_subscriptions(&self.db_pool, &self.jobs.pending_subscriptions)
+            .await
+            .unwrap()
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -155,9 +226,85 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    /// Extract the unsubscribe link from the plain-text body of an email.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .find(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+            .unwrap();
+        let mut unsubscribe_link = reqwest::Url::parse(link.as_str()).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
+    /// Extract the link to a data request from the plain-text body of an email.
+    pub fn get_data_request_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .find(|l| l.as_str().contains("/privacy/request"))
+            .unwrap();
+        let mut data_request_link = reqwest::Url::parse(link.as_str()).unwrap();
+        assert_eq!(data_request_link.host_str().unwrap(), "127.0.0.1");
+        data_request_link.set_port(Some(self.port)).unwrap();
+        data_request_link
+    }
+
+    pub async fn post_privacy(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/privacy", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_privacy_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/privacy", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_erase_personal_data(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/privacy/request/erase", &self.address))
+            .form(&serde_json::json!({ "token": token }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/resend_confirmation",
+                &self.address
+            ))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, unsubscribe_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&serde_json::json!({ "unsubscribe_token": unsubscribe_token }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn confirm_subscription(&self, body: String) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/subscriptions/confirm?{}", &self.address, body))
+            .get(format!("{}/subscriptions/confirm?{}", &self.address, body))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .send()
             .await
@@ -169,7 +316,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This `reqwest` method makes sure that the body is URL-encoded
             // and the `Content-Type` header is set accordingly.
             .form(body)
@@ -180,7 +327,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -191,7 +338,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -205,11 +352,10 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        let body = self.with_csrf_token(body).await;
         self.api_client
-            .post(&format!("{}/admin/change_password", &self.address))
-            // This `reqwest` method makes sure that the body is URL-encoded
-            // and the `Content-Type` header is set accordingly.
-            .form(body)
+            .post(format!("{}/admin/change_password", &self.address))
+            .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -217,7 +363,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/change_password", &self.address))
+            .get(format!("{}/admin/change_password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -227,9 +373,137 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
-    pub async fn get_logout(&self) -> reqwest::Response {
+    pub async fn post_logout(&self) -> reqwest::Response {
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
         self.api_client
-            .get(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_session<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let body = self.with_csrf_token(body).await;
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_logout_everywhere(&self) -> reqwest::Response {
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
+        self.api_client
+            .post(format!(
+                "{}/admin/sessions/logout_everywhere",
+                &self.address
+            ))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/api_tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.get_api_tokens().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let body = self.with_csrf_token(body).await;
+        self.api_client
+            .post(format!("{}/admin/api_tokens", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let body = self.with_csrf_token(body).await;
+        self.api_client
+            .post(format!("{}/admin/api_tokens/revoke", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create a token through the admin UI and return it in clear text.
+    /// The test user must be logged in.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let mut body = serde_json::json!({ "name": "CMS" });
+        for scope in scopes {
+            body[scope] = "on".into();
+        }
+        let html_page = self
+            .post_create_api_token(&body)
+            .await
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let start = html_page.find("<code>").unwrap() + "<code>".len();
+        let end = html_page.find("</code>").unwrap();
+        html_page[start..end].to_owned()
+    }
+
+    pub async fn post_api_newsletter_issue(
+        &self,
+        token: &str,
+        idempotency_key: Option<&str>,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .post(format!("{}/api/v1/newsletter_issues", &self.address))
+            .bearer_auth(token)
+            .json(body);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_newsletter_issue(
+        &self,
+        token: &str,
+        status_url: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, status_url))
+            .bearer_auth(token)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -239,9 +513,23 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        let body = self.with_csrf_token(body).await;
         self.api_client
-            .post(&format!("{}/admin/newsletter", &self.address))
-            .form(body)
+            .post(format!("{}/admin/newsletter", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preview_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let body = self.with_csrf_token(body).await;
+        self.api_client
+            .post(format!("{}/admin/newsletter/preview", &self.address))
+            .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -249,7 +537,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletter", &self.address))
+            .get(format!("{}/admin/newsletter", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -258,6 +546,123 @@ impl TestApp {
     pub async fn get_publish_newsletter_html(&self) -> String {
         self.get_publish_newsletter().await.text().await.unwrap()
     }
+
+    /// Publish an issue through the admin UI, listed in the archive if `is_public`.
+    pub async fn publish_issue(&self, title: &str, content_markdown: &str, is_public: bool) {
+        let mut body = serde_json::json!({
+            "title": title,
+            "content_markdown": content_markdown,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        });
+        if is_public {
+            body["is_public"] = "true".into();
+        }
+        let response = self.post_publish_newsletter(&body).await;
+        assert_is_redirect_to(&response, "/admin/newsletter");
+    }
+
+    pub async fn get_archive(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/archive{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self) -> String {
+        self.get_archive("").await.text().await.unwrap()
+    }
+
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is relative to `/admin`, e.g. `/subscribers/export?format=csv`.
+    pub async fn get_admin_export(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers_html(&self, query: &str) -> String {
+        self.get_admin_subscribers(query)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `resend_confirmation`, `unsubscribe` or `delete`.
+    pub async fn post_admin_subscriber_action(
+        &self,
+        action: &str,
+        subscriber_id: Uuid,
+    ) -> reqwest::Response {
+        let body = self
+            .with_csrf_token(&serde_json::json!({ "subscriber_id": subscriber_id }))
+            .await;
+        self.api_client
+            .post(format!("{}/admin/subscribers/{}", &self.address, action))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_erase_subscriber(&self, email: &str) -> reqwest::Response {
+        let body = self
+            .with_csrf_token(&serde_json::json!({ "email": email }))
+            .await;
+        self.api_client
+            .post(format!("{}/admin/subscribers/erase", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Upload `csv` as a `multipart/form-data` form, like a browser would.
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await.unwrap_or_default();
+        let boundary = "zero2prod-boundary";
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            {csrf_token}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            {mode}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
 async fn configure_database(config: &configuration::DatabaseSettings) -> PgPool {
@@ -0,0 +1,283 @@
+use chrono::{Duration, Utc};
+use claims::assert_matches;
+use uuid::Uuid;
+use zero2prod::{
+    email_outbox::{OutboxEmail, QueuedEmail},
+    issue_delivery_worker::DeliverIssue,
+    jobs::{
+        enqueue, enqueue_at, schedule_recurring, try_execute_job, ExecutionOutcome, Lane, Schedule,
+    },
+    pending_subscriptions::{PurgeStalePendingSubscriptions, SendPendingReminders},
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+struct QueuedJob {
+    job_id: Uuid,
+    kind: String,
+    n_attempts: i16,
+    due: Option<bool>,
+    schedule: Option<String>,
+    last_error: Option<String>,
+}
+
+async fn queued_jobs(app: &TestApp) -> Vec<QueuedJob> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+SELECT job_id, kind, n_attempts, run_at <= now() AS due, schedule, last_error
+FROM background_jobs
+ORDER BY created_at
+"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn make_queued_jobs_due(app: &TestApp) {
+    sqlx::query!("UPDATE background_jobs SET run_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn run_next_job(app: &TestApp, lane: Lane) -> ExecutionOutcome {
+    try_execute_job(&app.jobs, lane).await.unwrap()
+}
+
+/// For a subscriber who does not exist: it is skipped.
+fn email() -> QueuedEmail {
+    QueuedEmail {
+        subscriber_id: Uuid::new_v4(),
+        email: OutboxEmail::Confirmation,
+    }
+}
+
+fn delivery() -> DeliverIssue {
+    DeliverIssue {
+        newsletter_issue_id: Uuid::new_v4(),
+        subscriber_email: "ursula_le_guin@gmail.com".into(),
+    }
+}
+
+#[tokio::test]
+async fn jobs_do_not_run_before_they_are_due() {
+    // Given
+    let app = spawn_app().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enqueue_at(
+        &mut transaction,
+        &PurgeStalePendingSubscriptions,
+        Utc::now() + Duration::hours(1),
+    )
+    .await
+    .unwrap();
+    transaction.commit().await.unwrap();
+
+    // When
+    let outcome = run_next_job(&app, Lane::Bulk).await;
+
+    // Then
+    assert_matches!(outcome, ExecutionOutcome::EmptyQueue);
+    assert_eq!(queued_jobs(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn jobs_are_only_queued_once_the_transaction_is_committed() {
+    // Given
+    let app = spawn_app().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+
+    // When
+    enqueue(&mut transaction, &delivery()).await.unwrap();
+    transaction.rollback().await.unwrap();
+
+    // Then
+    assert!(queued_jobs(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn jobs_sharing_a_unique_key_are_queued_once() {
+    // Given
+    let app = spawn_app().await;
+    let job = delivery();
+    let mut transaction = app.db_pool.begin().await.unwrap();
+
+    // When
+    let first = enqueue(&mut transaction, &job).await.unwrap();
+    let second = enqueue(&mut transaction, &job).await.unwrap();
+    let other = enqueue(&mut transaction, &delivery()).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    // Then
+    assert!(first);
+    assert!(!second);
+    assert!(other);
+    assert_eq!(queued_jobs(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn higher_priority_jobs_run_first() {
+    // Given
+    let app = spawn_app().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enqueue(&mut transaction, &SendPendingReminders)
+        .await
+        .unwrap();
+    enqueue(&mut transaction, &email()).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    // When
+    let outcome = run_next_job(&app, Lane::Transactional).await;
+
+    // Then
+    assert_matches!(outcome, ExecutionOutcome::TaskCompleted);
+    let jobs = queued_jobs(&app).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].kind, "send_pending_subscription_reminders");
+}
+
+#[tokio::test]
+async fn transactional_workers_leave_bulk_jobs_alone() {
+    // Given
+    let app = spawn_app().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enqueue(&mut transaction, &delivery()).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    // When
+    let outcome = run_next_job(&app, Lane::Transactional).await;
+
+    // Then
+    assert_matches!(outcome, ExecutionOutcome::EmptyQueue);
+    assert_eq!(queued_jobs(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn bulk_workers_run_transactional_jobs_first() {
+    // Given
+    let app = spawn_app().await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enqueue(&mut transaction, &delivery()).await.unwrap();
+    enqueue(&mut transaction, &email()).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    // When
+    let outcome = run_next_job(&app, Lane::Bulk).await;
+
+    // Then
+    assert_matches!(outcome, ExecutionOutcome::TaskCompleted);
+    let jobs = queued_jobs(&app).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].kind, "deliver_issue");
+}
+
+#[tokio::test]
+async fn failed_jobs_are_retried_then_given_up_on() {
+    // Given
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+INSERT INTO background_jobs (job_id, kind, lane, payload, run_at, max_attempts, created_at)
+VALUES ($1, 'no_such_job', 'bulk', '{}', now(), 2, now())
+"#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // When
+    run_next_job(&app, Lane::Bulk).await;
+    let after_first_attempt = queued_jobs(&app).await;
+    make_queued_jobs_due(&app).await;
+    run_next_job(&app, Lane::Bulk).await;
+
+    // Then
+    assert_eq!(after_first_attempt.len(), 1);
+    let job = &after_first_attempt[0];
+    assert_eq!(job.n_attempts, 1);
+    assert_eq!(job.due, Some(false));
+    assert!(job
+        .last_error
+        .as_deref()
+        .unwrap()
+        .contains("not a known kind of job"));
+    assert!(queued_jobs(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn recurring_jobs_are_scheduled_once() {
+    // Given
+    let app = spawn_app().await;
+    let schedule = Schedule::parse("@every 1h").unwrap();
+
+    // When
+    for _ in 0..2 {
+        schedule_recurring(&app.db_pool, &PurgeStalePendingSubscriptions, &schedule)
+            .await
+            .unwrap();
+    }
+
+    // Then
+    let jobs = queued_jobs(&app).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].due, Some(false));
+    assert_eq!(jobs[0].schedule.as_deref(), Some("@every 3600s"));
+}
+
+#[tokio::test]
+async fn recurring_jobs_queue_their_next_run() {
+    // Given
+    let app = spawn_app().await;
+    let schedule = Schedule::parse("@every 1h").unwrap();
+    schedule_recurring(&app.db_pool, &PurgeStalePendingSubscriptions, &schedule)
+        .await
+        .unwrap();
+    let first_run = queued_jobs(&app).await.remove(0);
+    make_queued_jobs_due(&app).await;
+
+    // When
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let jobs = queued_jobs(&app).await;
+    assert_eq!(jobs.len(), 1);
+    assert_ne!(jobs[0].job_id, first_run.job_id);
+    assert_eq!(jobs[0].kind, first_run.kind);
+    assert_eq!(jobs[0].due, Some(false));
+    assert_eq!(jobs[0].schedule, first_run.schedule);
+}
+
+#[tokio::test]
+async fn recurring_jobs_move_to_their_new_schedule() {
+    // Given
+    let app = spawn_app().await;
+    schedule_recurring(
+        &app.db_pool,
+        &PurgeStalePendingSubscriptions,
+        &Schedule::parse("@every 1h").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // When
+    schedule_recurring(
+        &app.db_pool,
+        &PurgeStalePendingSubscriptions,
+        &Schedule::parse("@every 10m").unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // Then
+    let job = sqlx::query!(
+        r#"SELECT schedule, run_at <= now() + interval '10 minutes' AS "moved!" FROM background_jobs"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(job.schedule.as_deref(), Some("@every 600s"));
+    assert!(job.moved);
+}
@@ -0,0 +1,96 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn the_stylesheet_is_served() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/static/style.css", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/css; charset=utf-8"
+    );
+}
+
+#[tokio::test]
+async fn every_page_links_the_stylesheet() {
+    // Given
+    let app = spawn_app().await;
+    let login_page = app.get_login_html().await;
+    app.login_admin().await;
+
+    // When
+    let pages = vec![
+        login_page,
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_publish_newsletter_html().await,
+        app.get_sessions_html().await,
+        app.get_api_tokens_html().await,
+    ];
+
+    // Then
+    for html_page in pages {
+        assert!(html_page.contains(r#"<link rel="stylesheet" href="/static/style.css">"#));
+    }
+}
+
+#[tokio::test]
+async fn admin_pages_share_the_navigation() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let pages = vec![
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_publish_newsletter_html().await,
+        app.get_sessions_html().await,
+        app.get_api_tokens_html().await,
+    ];
+
+    // Then
+    for html_page in pages {
+        assert!(html_page.contains(r#"<a href="/admin/newsletter">"#));
+        assert!(html_page.contains(r#"<form action="/admin/logout" method="post">"#));
+    }
+}
+
+#[tokio::test]
+async fn user_provided_values_are_escaped() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When - Part 1 - Create a token with a malicious name
+    let html_page = app
+        .post_create_api_token(&serde_json::json!({
+            "name": "<script>alert(1)</script>",
+            "issues:read": "on",
+        }))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Then
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;"));
+
+    // When - Part 2 - List tokens
+    let html_page = app.get_api_tokens_html().await;
+
+    // Then
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;"));
+}
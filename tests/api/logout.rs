@@ -6,7 +6,7 @@ async fn you_must_be_logged_in_to_logout() {
     let app = spawn_app().await;
 
     // When
-    let response = app.get_logout().await;
+    let response = app.post_logout().await;
 
     // Then
     assert_is_redirect_to(&response, "/login");
@@ -19,7 +19,7 @@ async fn logged_in_user_can_logout() {
     app.login_admin().await;
 
     // When
-    let response = app.get_logout().await;
+    let response = app.post_logout().await;
 
     // Then
     assert_is_redirect_to(&response, "/login");
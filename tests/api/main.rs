@@ -1,8 +1,24 @@
+mod admin_exports;
+mod admin_subscribers;
+mod admin_subscribers_import;
+mod api_tokens;
+mod archive;
+mod consent;
+mod csrf;
+mod email_outbox;
 mod health_check;
 mod helpers;
+mod jobs;
+mod layout;
 mod login;
 mod logout;
 mod newsletter;
 mod password;
+mod pending_subscriptions;
+mod privacy;
+mod security_headers;
+mod sessions;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
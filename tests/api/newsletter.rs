@@ -1,16 +1,26 @@
 use std::time::Duration;
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, ConfirmationLinks, TestApp,
+};
 use fake::{
     faker::{internet::en::SafeEmail, name::en::Name},
     Fake,
 };
-use wiremock::matchers::{any, method, path};
+use wiremock::matchers::{any, body_partial_json, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 const PUBLISH_SUCCESS_MESSAGE: &str = "<p><i>The newsletter issue has been accepted - \
                                             emails will go out shortly.</i></p>";
 
+/// The digest the page asks to submit back to confirm its warnings.
+fn confirm_warnings_digest(html_page: &str) -> String {
+    let field = r#"name="confirm_warnings" value=""#;
+    let start = html_page.find(field).unwrap() + field.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_owned()
+}
+
 #[tokio::test]
 async fn you_must_be_logged_in_to_issue_a_newsletter() {
     // Given
@@ -18,8 +28,7 @@ async fn you_must_be_logged_in_to_issue_a_newsletter() {
 
     let issue_newsletter_body = serde_json::json!({
         "title": "Newsletter title",
-        "content_text": "Newsletter body as plain text",
-        "content_html": "<p>Newsletter body as HTML</p>",
+        "content_markdown": "Newsletter body as **Markdown**",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
 
@@ -41,8 +50,7 @@ async fn newsletters_returns_400_for_invalid_data() {
     let test_cases = vec![
         (
             serde_json::json!({
-                "content_text": "Newsletter body as plain text",
-                "content_html": "<p>Newsletter body as HTML</p>",
+                "content_markdown": "Newsletter body as **Markdown**",
                 "idempotency_key": uuid::Uuid::new_v4().to_string(),
             }),
             "missing title",
@@ -57,8 +65,7 @@ async fn newsletters_returns_400_for_invalid_data() {
         (
             serde_json::json!({
                 "title": "Newsletter!",
-                "content_text": "Newsletter body as plain text",
-                "content_html": "<p>Newsletter body as HTML</p>",
+                "content_markdown": "Newsletter body as **Markdown**",
             }),
             "missing idempotency key",
         ),
@@ -95,8 +102,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // When
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
-        "content_text": "Newsletter body as plain text",
-        "content_html": "<p>Newsletter body as HTML</p>",
+        "content_markdown": "Newsletter body as **Markdown**",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
 
@@ -123,8 +129,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // When
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
-        "content_text": "Newsletter body as plain text",
-        "content_html": "<p>Newsletter body as HTML</p>",
+        "content_markdown": "Newsletter body as **Markdown**",
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
 
@@ -134,6 +139,40 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Mock verifies on Drop that we sent the newsletter email
 }
 
+#[tokio::test]
+async fn each_lane_sends_through_its_own_message_stream() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.email_client.transactional_message_stream = Some("outbound".into());
+        c.email_client.bulk_message_stream = Some("broadcast".into());
+    })
+    .await;
+    app.login_admin().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(body_partial_json(
+        serde_json::json!({ "MessageStream": "broadcast" }),
+    ))
+    .respond_with(ResponseTemplate::new(200))
+    .expect(1)
+    .mount(&app.email_server)
+    .await;
+
+    // When
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "Newsletter body as **Markdown**",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let confirmation_email = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&confirmation_email.body).unwrap();
+    assert_eq!(body["MessageStream"], "outbound");
+}
+
 #[tokio::test]
 async fn newsletter_creation_is_idempotent() {
     // Arrange
@@ -151,8 +190,7 @@ async fn newsletter_creation_is_idempotent() {
     // When - Part 1 - Submit newsletter form
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
-        "content_text": "Newsletter body as plain text",
-        "content_html": "<p>Newsletter body as HTML</p>",
+        "content_markdown": "Newsletter body as **Markdown**",
         "idempotency_key": uuid::Uuid::new_v4().to_string()
     });
 
@@ -194,8 +232,7 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // When - Submit two newsletter forms concurrently
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
-        "content_text": "Newsletter body as plain text",
-        "content_html": "<p>Newsletter body as HTML</p>",
+        "content_markdown": "Newsletter body as **Markdown**",
         "idempotency_key": uuid::Uuid::new_v4().to_string()
     });
 
@@ -213,9 +250,323 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
 
+#[tokio::test]
+async fn newsletters_are_personalised_for_each_subscriber() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT name, unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ name }}",
+        "content_markdown": "Hi {{ name }}!",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], format!("News for {}", subscriber.name));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains(&format!("Hi {}!", subscriber.name)));
+    assert!(text_body.contains(&format!(
+        "/subscriptions/unsubscribe?unsubscribe_token={}",
+        subscriber.unsubscribe_token
+    )));
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?unsubscribe_token="));
+}
+
+#[tokio::test]
+async fn newsletters_with_unknown_template_variables_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "Hi {{ first_name }}!",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    // Then
+    assert_post_redirect_with_message(&newsletter_request_body, "first_name", &app).await;
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn issues_are_stored_with_html_and_text_rendered_from_markdown() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let mut newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "# Hello\n\nSome **bold** news.\n\n<script>alert(1)</script>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let html_page = app
+        .post_preview_newsletter(&newsletter_request_body)
+        .await
+        .text()
+        .await
+        .unwrap();
+    newsletter_request_body["confirm_warnings"] = confirm_warnings_digest(&html_page).into();
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+
+    // Then
+    let saved =
+        sqlx::query!("SELECT markdown_content, html_content, text_content FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        saved.markdown_content.as_deref(),
+        newsletter_request_body["content_markdown"].as_str()
+    );
+    assert!(saved.html_content.contains("<h1>Hello</h1>"));
+    assert!(saved.html_content.contains("<strong>bold</strong>"));
+    assert!(!saved.html_content.contains("<script>"));
+    assert!(saved.text_content.contains("Some bold news."));
+    assert!(!saved.text_content.contains("**"));
+}
+
+#[tokio::test]
+async fn issues_with_warnings_must_be_confirmed_before_being_published() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let mut newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "Hello!\n\n<script>alert(1)</script>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    // When - Part 1 - Publish without confirmation
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("have been removed: script"));
+    let digest = confirm_warnings_digest(&html_page);
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+
+    // When - Part 2 - Confirm after editing the content
+    newsletter_request_body["confirm_warnings"] = digest.clone().into();
+    newsletter_request_body["content_markdown"] =
+        "Hello again!\n\n<script>alert(1)</script>".into();
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Then - The warnings are shown again
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Please review before publishing"));
+
+    // When - Part 3 - Confirm the reviewed content
+    newsletter_request_body["confirm_warnings"] = digest.into();
+    newsletter_request_body["content_markdown"] = "Hello!\n\n<script>alert(1)</script>".into();
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+
+    // Then
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn issues_outlive_the_account_of_their_author() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "Newsletter body",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+
+    // When
+    let user_id = app.test_user.user_id;
+    for query in [
+        "DELETE FROM idempotency WHERE user_id = $1",
+        "DELETE FROM user_sessions WHERE user_id = $1",
+        "DELETE FROM users WHERE user_id = $1",
+    ] {
+        sqlx::query(query)
+            .bind(user_id)
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+
+    // Then
+    let issue = sqlx::query!("SELECT title, author_user_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    assert_eq!(issue.author_user_id, None);
+}
+
+#[tokio::test]
+async fn style_blocks_are_inlined_in_stored_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_markdown": "<style>p { color: red; }</style>\n\nHello!",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    assert_post_redirect_with_message(&newsletter_request_body, PUBLISH_SUCCESS_MESSAGE, &app)
+        .await;
+
+    // Then
+    let saved = sqlx::query!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved
+        .html_content
+        .contains(r#"<p style="color: red;">Hello!</p>"#));
+    assert!(!saved.html_content.contains("<style>"));
+}
+
+#[tokio::test]
+async fn the_preview_shows_the_issue_as_it_will_be_delivered() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // When
+    let response = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "News for {{ name }}",
+            "content_markdown": "Hi **{{ name }}**!",
+            "idempotency_key": idempotency_key,
+        }))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("News for Jane Doe"));
+    // The HTML body is embedded, escaped, in the `srcdoc` of an iframe
+    assert!(html_page.contains("&lt;strong&gt;Jane Doe&lt;/strong&gt;"));
+    assert!(html_page.contains("Hi Jane Doe!"));
+    assert!(html_page.contains("/subscriptions/unsubscribe?unsubscribe_token=preview"));
+    // The form is pre-filled to keep editing or publish
+    assert!(html_page.contains(&format!(
+        r#"name="idempotency_key" value="{}""#,
+        idempotency_key
+    )));
+    assert!(html_page.contains(">Hi **{{ name }}**!</textarea>"));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn the_preview_allows_the_inline_styles_of_emails() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let form = app.get_publish_newsletter().await;
+    let preview = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "content_markdown": "Newsletter body",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Then
+    let csp = |response: &reqwest::Response| {
+        response.headers()["Content-Security-Policy"]
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+    assert!(csp(&preview).contains("style-src 'self' 'unsafe-inline'"));
+    assert!(!csp(&form).contains("style-src"));
+}
+
+#[tokio::test]
+async fn the_preview_reports_template_errors() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+
+    // When
+    let response = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "content_markdown": "Hi {{ first_name }}!",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("first_name"));
+}
+
 /// Use the public API of the application under test to create
 /// an unconfirmed subscriber.
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
     let body = serde_urlencoded::to_string(serde_json::json!({
@@ -236,6 +587,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .await
         .error_for_status()
         .unwrap();
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app
         .email_server
@@ -250,7 +602,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
 
 /// Use the public API of the application under test to create
 /// a confirmed subscriber.
-async fn create_confirmed_subscriber(app: &TestApp) {
+pub async fn create_confirmed_subscriber(app: &TestApp) {
     let confirmation_links = create_unconfirmed_subscriber(app).await;
 
     // Confirm subscriber
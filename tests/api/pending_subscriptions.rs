@@ -0,0 +1,208 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp, email: &str) {
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}",
+        email.replace('@', "%40")
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+/// Pretend the subscriptions became pending `hours` ago.
+async fn age_pending_subscriptions(app: &TestApp, hours: i32) {
+    sqlx::query!(
+        "UPDATE subscriptions SET pending_since = now() - make_interval(hours => $1)",
+        hours
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn mock_email_api(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn n_sent_emails(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn unconfirmed_subscribers_are_reminded_once_with_a_working_link() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    age_pending_subscriptions(&app, 49).await;
+
+    // When
+    app.send_all_pending_reminders().await;
+    app.send_all_pending_reminders().await;
+
+    // Then
+    assert_eq!(n_sent_emails(&app).await, 2);
+    let reminder = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&reminder.body).unwrap();
+    assert_eq!(body["Subject"], "Please confirm your subscription");
+    let confirmation_links = app.get_confirmation_links(reminder);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribers_are_not_reminded_before_the_delay() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    age_pending_subscriptions(&app, 47).await;
+
+    // When
+    app.send_all_pending_reminders().await;
+
+    // Then
+    assert_eq!(n_sent_emails(&app).await, 1);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_not_reminded() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    age_pending_subscriptions(&app, 49).await;
+
+    // When
+    app.send_all_pending_reminders().await;
+
+    // Then
+    assert_eq!(n_sent_emails(&app).await, 1);
+}
+
+#[tokio::test]
+async fn subscriptions_still_pending_after_the_retention_period_are_deleted() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "stale@example.com").await;
+    age_pending_subscriptions(&app, 31 * 24).await;
+    subscribe(&app, "recent@example.com").await;
+
+    // When
+    let n_purged = app.purge_stale_pending_subscriptions().await;
+
+    // Then
+    assert_eq!(n_purged, 1);
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(emails, vec!["recent@example.com"]);
+    let n_tokens = sqlx::query!(
+        r#"SELECT count(*) AS "count!" FROM subscription_tokens t
+        LEFT JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.id IS NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_tokens, 0);
+    let consent_emails: Vec<String> = sqlx::query!("SELECT email FROM consent_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(consent_emails, vec!["recent@example.com"]);
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_restarts_the_schedule() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed', pending_since = now() - interval '60 days'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // When
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    app.send_all_pending_reminders().await;
+    let n_purged = app.purge_stale_pending_subscriptions().await;
+
+    // Then
+    assert_eq!(n_purged, 0);
+    assert_eq!(n_sent_emails(&app).await, 2);
+}
+
+#[tokio::test]
+async fn purged_subscribers_who_once_confirmed_keep_their_consent_events() {
+    // Given
+    let app = spawn_app().await;
+    mock_email_api(&app).await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    age_pending_subscriptions(&app, 31 * 24).await;
+
+    // When
+    let n_purged = app.purge_stale_pending_subscriptions().await;
+
+    // Then
+    assert_eq!(n_purged, 1);
+    let actions: Vec<String> =
+        sqlx::query!("SELECT action FROM consent_events ORDER BY occurred_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.action)
+            .collect();
+    assert_eq!(actions, vec!["subscribed", "confirmed", "subscribed"]);
+}
@@ -0,0 +1,183 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+/// Ask for the data stored about `email`, and return the link sent to it.
+async fn request_data(app: &TestApp, email: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_privacy(email).await;
+    assert_is_redirect_to(&response, "/privacy");
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_data_request_link(&email_request)
+}
+
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn no_link_is_sent_to_unknown_addresses() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_privacy("nobody@example.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/privacy");
+    // The page does not tell whether the address is known.
+    assert!(app
+        .get_privacy_html()
+        .await
+        .contains("If we store any data about this address"));
+}
+
+#[tokio::test]
+async fn data_requests_are_rate_limited_per_address() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    for _ in 0..5 {
+        let response = app.post_privacy(&email).await;
+        assert_is_redirect_to(&response, "/privacy");
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Then - The mock asserts on drop
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_through_the_emailed_link() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_data(&app, &email).await;
+
+    // When
+    let page = reqwest::get(link.clone()).await.unwrap();
+    let export = reqwest::get(format!(
+        "{}/privacy/request/export?token={}",
+        app.address,
+        token(&link)
+    ))
+    .await
+    .unwrap();
+
+    // Then
+    assert_eq!(page.status().as_u16(), 200);
+    assert!(page.text().await.unwrap().contains(&email));
+    assert_eq!(export.status().as_u16(), 200);
+    assert_eq!(export.headers()["Content-Type"], "application/json");
+    let data: serde_json::Value = export.json().await.unwrap();
+    assert_eq!(data["email"], email.as_str());
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    // The confirmation link, used already
+    let tokens = data["subscription_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert!(tokens[0]["consumed_at"].is_string());
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data_through_the_emailed_link() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_data(&app, &email).await;
+
+    // When
+    let response = app.post_erase_personal_data(&token(&link)).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+    let n_tombstones = sqlx::query!(r#"SELECT count(*) AS "count!" FROM erased_subscribers"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tombstones, 1);
+    // The link cannot be used anymore.
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_or_unknown_links_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_data(&app, &email).await;
+    sqlx::query!("UPDATE data_request_tokens SET created_at = now() - interval '2 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let expired = app.post_erase_personal_data(&token(&link)).await;
+    let unknown = reqwest::get(format!(
+        "{}/privacy/request/export?token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Then
+    assert_eq!(expired.status().as_u16(), 401);
+    assert_eq!(unknown.status().as_u16(), 401);
+    let n_subscribers = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 1);
+}
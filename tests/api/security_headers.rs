@@ -0,0 +1,68 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn html_pages_are_served_with_security_headers() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    let headers = response.headers();
+    let csp = headers["Content-Security-Policy"].to_str().unwrap();
+    assert!(csp.contains("default-src 'self'"));
+    assert!(csp.contains("report-uri /csp_report"));
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(
+        headers["Referrer-Policy"],
+        "strict-origin-when-cross-origin"
+    );
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+    // HSTS is only configured in production
+    assert!(!headers.contains_key("Strict-Transport-Security"));
+}
+
+#[tokio::test]
+async fn redirects_to_the_login_page_have_security_headers() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_admin_dashboard().await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(response.headers().contains_key("Content-Security-Policy"));
+}
+
+#[tokio::test]
+async fn csp_reports_are_accepted() {
+    // Given
+    let app = spawn_app().await;
+    let report = serde_json::json!({
+        "csp-report": {
+            "document-uri": "http://127.0.0.1/login",
+            "violated-directive": "script-src",
+            "blocked-uri": "https://evil.example.com/script.js"
+        }
+    });
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/csp_report", &app.address))
+        .header("Content-Type", "application/csp-report")
+        .body(report.to_string())
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 204);
+}
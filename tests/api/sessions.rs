@@ -0,0 +1,217 @@
+use crate::helpers::{assert_is_redirect_to, new_api_client, spawn_app, TestApp};
+
+/// Log the test user in from another device, returning its client.
+async fn login_from_another_device(app: &TestApp) -> reqwest::Client {
+    let client = new_api_client();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "another-device")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard_with(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_sessions().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn active_sessions_are_listed_with_their_metadata() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    login_from_another_device(&app).await;
+
+    // When
+    let html_page = app.get_sessions_html().await;
+
+    // Then
+    assert!(html_page.contains("Current session"));
+    assert!(html_page.contains("another-device"));
+    assert!(html_page.contains("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn session_metadata_is_escaped() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let client = new_api_client();
+    client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "<script>alert(1)</script>")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // When
+    let html_page = app.get_sessions_html().await;
+
+    // Then
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;"));
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let other_device = login_from_another_device(&app).await;
+    let other_session_id =
+        sqlx::query!("SELECT session_id FROM user_sessions WHERE user_agent = 'another-device'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .session_id;
+
+    // When
+    let response = app
+        .post_revoke_session(&serde_json::json!({ "session_id": other_session_id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    // Then
+    let response = get_dashboard_with(&app, &other_device).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_everywhere_revokes_every_session() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let other_device = login_from_another_device(&app).await;
+
+    // When
+    let response = app.post_logout_everywhere().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Then
+    let response = get_dashboard_with(&app, &other_device).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn changing_password_revokes_other_sessions() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    let other_device = login_from_another_device(&app).await;
+
+    // When
+    let new_password = "new-random-password";
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_confirmation": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/change_password");
+
+    // Then
+    let response = get_dashboard_with(&app, &other_device).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_idle_session_expires_with_an_explanation() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    sqlx::query!("UPDATE user_sessions SET last_seen_at = now() - interval '31 minutes'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = app.get_admin_dashboard().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your session expired after 30 minutes of inactivity"));
+    // The session is gone for good
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn idle_sessions_are_not_listed() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    login_from_another_device(&app).await;
+    sqlx::query!(
+        "UPDATE user_sessions SET last_seen_at = now() - interval '31 minutes' \
+        WHERE user_agent = 'another-device'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // When
+    let html_page = app.get_sessions_html().await;
+
+    // Then
+    assert!(html_page.contains("Current session"));
+    assert!(!html_page.contains("another-device"));
+    let n_sessions = sqlx::query!(r#"SELECT count(*) AS "count!" FROM user_sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sessions, 1);
+}
+
+#[tokio::test]
+async fn an_old_session_expires_even_if_it_is_in_use() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    sqlx::query!("UPDATE user_sessions SET created_at = now() - interval '13 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = app.get_admin_dashboard().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your session expired after 12 hours"));
+}
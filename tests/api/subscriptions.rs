@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
@@ -36,13 +36,6 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .expect(1)
-        .mount(&app.email_server)
-        .await;
-
     // When
     let response = app.post_subscriptions(body.into()).await;
 
@@ -56,13 +49,6 @@ async fn subscribe_persists_the_new_subscriber() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .expect(1)
-        .mount(&app.email_server)
-        .await;
-
     // When
     app.post_subscriptions(body.into()).await;
 
@@ -116,6 +102,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
 
     // When
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Then
     // Mock asserts on drop
@@ -134,6 +121,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // When
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Then
     // Get the first intercepted request
@@ -161,3 +149,256 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Then
     assert_eq!(response.status().as_u16(), 500);
 }
+
+async fn last_email_body(app: &TestApp) -> serde_json::Value {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&email_request.body).unwrap()
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_sends_a_new_confirmation_email() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // When
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let confirmation_links =
+        app.get_confirmation_links(&app.email_server.received_requests().await.unwrap()[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_sends_a_notice() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let confirmation_links =
+        app.get_confirmation_links(&app.email_server.received_requests().await.unwrap()[0]);
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    // When
+    let response = app
+        .post_subscriptions("name=someone%20else&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let email = last_email_body(&app).await;
+    assert_eq!(email["Subject"], "You are already subscribed");
+    let text_body = email["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Hello le guin!"));
+    assert!(text_body.contains("/subscriptions/unsubscribe?unsubscribe_token="));
+    assert!(!text_body.contains("/subscriptions/confirm"));
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_sends_one_notice_per_hour() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        let response = app.post_subscriptions(body.into()).await;
+        responses.push((response.status(), response.text().await.unwrap()));
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    for response in &responses {
+        assert_eq!(response, &responses[0]);
+    }
+    let received = app.email_server.received_requests().await.unwrap();
+    assert_eq!(received.len(), 2);
+    assert_eq!(
+        last_email_body(&app).await["Subject"],
+        "You are already subscribed"
+    );
+}
+
+#[tokio::test]
+async fn unsubscribed_people_must_confirm_when_subscribing_again() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "pending_confirmation");
+    let confirmation_links =
+        app.get_confirmation_links(&app.email_server.received_requests().await.unwrap()[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_responds_the_same_whatever_the_state_of_the_subscriber() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let mut responses = Vec::new();
+    for status in ["pending_confirmation", "confirmed", "unsubscribed"] {
+        // When
+        let response = app.post_subscriptions(body.into()).await;
+        responses.push((response.status(), response.text().await.unwrap()));
+        sqlx::query!("UPDATE subscriptions SET status = $1", status)
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    let response = app.post_subscriptions(body.into()).await;
+    responses.push((response.status(), response.text().await.unwrap()));
+
+    // Then
+    for response in &responses {
+        assert_eq!(response, &responses[0]);
+    }
+    assert_eq!(responses[0].0.as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_database_rejects_unknown_statuses() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+
+    // When
+    let result = sqlx::query!("UPDATE subscriptions SET status = 'gone'")
+        .execute(&app.db_pool)
+        .await;
+
+    // Then
+    assert!(result.is_err());
+    assert_eq!(subscriber_status(&app).await, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_renders_a_check_your_inbox_page() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("click on the link it contains to confirm your subscription"));
+}
+
+#[tokio::test]
+async fn the_check_your_inbox_page_can_be_hosted_on_another_site() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.subscription_pages.check_inbox_url = Some("https://example.com/check-inbox".into());
+    })
+    .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Then
+    assert_is_redirect_to(&response, "https://example.com/check-inbox");
+}
@@ -3,7 +3,9 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use zero2prod::configuration::WelcomeEmailSettings;
+
+use crate::helpers::{assert_is_redirect_to, new_api_client, spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -11,7 +13,7 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
     let app = spawn_app().await;
 
     // When
-    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+    let response = reqwest::get(format!("{}/subscriptions/confirm", app.address))
         .await
         .unwrap();
 
@@ -32,6 +34,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -55,6 +58,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -77,7 +81,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 }
 
 #[tokio::test]
-async fn reclicking_on_the_confirmation_link_returns_200() {
+async fn confirmation_links_opened_again_show_the_confirmed_page() {
     // Given
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
@@ -89,6 +93,7 @@ async fn reclicking_on_the_confirmation_link_returns_200() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -99,14 +104,43 @@ async fn reclicking_on_the_confirmation_link_returns_200() {
         .unwrap();
 
     // When
-    let response = reqwest::get(confirmation_links.html)
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your subscription is confirmed"));
+}
+
+#[tokio::test]
+async fn used_confirmation_links_cannot_undo_an_unsubscription() {
+    // Given
+    let app = spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+    reqwest::get(confirmation_link.clone())
         .await
         .unwrap()
         .error_for_status()
         .unwrap();
+    let subscriber = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_unsubscribe(&subscriber.unsubscribe_token).await;
+
+    // When
+    let response = reqwest::get(confirmation_link).await.unwrap();
 
     // Then
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
 }
 
 #[tokio::test]
@@ -135,11 +169,12 @@ async fn confirm_fails_if_there_is_a_fatal_database_error() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
     // Sabotage the database
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN token_hash;",)
         .execute(&app.db_pool)
         .await
         .unwrap();
@@ -150,3 +185,242 @@ async fn confirm_fails_if_there_is_a_fatal_database_error() {
     // Then
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn confirmation_tokens_are_not_stored_in_clear() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    let stored = sqlx::query!("SELECT token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+    assert!(!stored.token_hash.contains(&token));
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_offer_to_send_a_new_one() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"<form action="/subscriptions/resend_confirmation" method="post">"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn confirmation_links_cannot_undo_an_unsubscription() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let subscriber = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_unsubscribe(&subscriber.unsubscribe_token).await;
+
+    // When
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 409);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+/// Subscribe, and return the confirmation link that was emailed.
+async fn subscribe(app: &TestApp) -> reqwest::Url {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
+
+#[tokio::test]
+async fn confirming_renders_a_confirmation_page() {
+    // Given
+    let app = spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+
+    // When
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your subscription is confirmed"));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_links_render_an_invalid_link_page() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=aaaaaaaaaaaaaaaaaaaaaaaaa",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This confirmation link is not valid"));
+}
+
+#[tokio::test]
+async fn confirmation_pages_can_be_hosted_on_another_site() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.subscription_pages.confirmed_url = Some("https://example.com/welcome".into());
+        c.subscription_pages.invalid_link_url = Some("https://example.com/oops".into());
+    })
+    .await;
+    let confirmation_link = subscribe(&app).await;
+
+    // When
+    let client = new_api_client();
+    let confirmed = client.get(confirmation_link).send().await.unwrap();
+    let unknown = client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=aaaaaaaaaaaaaaaaaaaaaaaaa",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&confirmed, "https://example.com/welcome");
+    assert_is_redirect_to(&unknown, "https://example.com/oops");
+}
+
+#[tokio::test]
+async fn a_welcome_email_is_sent_once_confirmed_when_configured() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.welcome_email = Some(WelcomeEmailSettings {
+            subject: "Welcome, {{ name }}".into(),
+            html_body: "<p>Glad to have you</p>".into(),
+            text_body: "Glad to have you".into(),
+        });
+    })
+    .await;
+    let confirmation_link = subscribe(&app).await;
+
+    // When
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(body["Subject"], "Welcome, le guin");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Glad to have you"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?unsubscribe_token="));
+}
+
+#[tokio::test]
+async fn no_welcome_email_is_sent_by_default() {
+    // Given
+    let app = spawn_app().await;
+    let confirmation_link = subscribe(&app).await;
+
+    // When
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+}
@@ -0,0 +1,129 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn subscribe(app: &TestApp) {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+async fn n_sent_emails(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn pending_subscribers_get_a_new_confirmation_link() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app).await;
+
+    // When
+    let response = app.post_resend_confirmation(EMAIL).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unknown_and_confirmed_addresses_get_the_same_page_and_no_email() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app).await;
+    let pending = app.post_resend_confirmation(EMAIL).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let confirmed = app.post_resend_confirmation(EMAIL).await;
+    let unknown = app.post_resend_confirmation("nobody@example.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(n_sent_emails(&app).await, 2);
+    let pending_page = pending.text().await.unwrap();
+    assert_eq!(confirmed.status().as_u16(), 200);
+    assert_eq!(confirmed.text().await.unwrap(), pending_page);
+    assert_eq!(unknown.status().as_u16(), 200);
+    assert_eq!(unknown.text().await.unwrap(), pending_page);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_rate_limited_per_address() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app).await;
+
+    // When
+    for _ in 0..5 {
+        let response = app.post_resend_confirmation(EMAIL).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    // One email when subscribing, and two more: queued emails count as well.
+    assert_eq!(n_sent_emails(&app).await, 3);
+}
+
+#[tokio::test]
+async fn subscribing_again_counts_towards_the_rate_limit() {
+    // Given
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app).await;
+
+    // When
+    for _ in 0..5 {
+        let response = app
+            .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    assert_eq!(n_sent_emails(&app).await, 3);
+}
@@ -0,0 +1,100 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+
+async fn publish_and_deliver_issue(app: &TestApp) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "content_markdown": "Newsletter body as **Markdown**",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_asks_for_confirmation() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_and_deliver_issue(&app).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link(&email_request);
+
+    // When
+    let response = reqwest::get(unsubscribe_link).await.unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_no_longer_receive_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.login_admin().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // No issue must be sent
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_unsubscribe(&unsubscribe_token).await;
+    publish_and_deliver_issue(&app).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unknown_unsubscribe_tokens_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.post_unsubscribe("not-a-real-token").await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 401);
+}